@@ -1,36 +1,64 @@
-use crate::data::KnownSources;
 use crate::data::SantaData;
 use crate::data::SourceList;
-use crate::sources::PackageSource;
+use crate::data::{constants, validate_data, KnownSources};
+use crate::history::{append_history, InstallHistoryEntry, SourceInstallRecord};
+use crate::sources::{InstallOutcome, PackageSource};
 use crate::traits::Exportable;
 use crate::{configuration::SantaConfig, sources::PackageCache};
+use anyhow::bail;
 use std::collections::HashSet;
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
 use std::{collections::HashMap, fmt::format};
 
 use log::{debug, error, info, trace, warn};
 
 use colored::*;
 
-pub fn status_command(config: &SantaConfig, data: &SantaData, mut cache: PackageCache, all: &bool) {
-    // filter sources to those enabled in the config
+pub fn status_command(
+    config: &SantaConfig,
+    data: &SantaData,
+    mut cache: PackageCache,
+    all: &bool,
+    source_filter: &[String],
+    available_only: bool,
+) {
+    for name in source_filter {
+        if !data.sources.iter().any(|source| &source.name_str() == name) {
+            eprintln!("{} unknown source '{}'", "warning:".yellow(), name);
+        }
+    }
+
+    // filter sources to those enabled in the config, and to those requested on the command line
     let sources: SourceList = data
         .sources
         .clone()
         .into_iter()
         .filter(|source| config.clone().source_is_enabled(source))
+        .filter(|source| source_filter.is_empty() || source_filter.contains(&source.name_str()))
+        .filter(|source| !available_only || source.is_available())
         .collect();
     // let serialized = serde_yaml::to_string(&sources).unwrap();
 
     for source in &sources {
         cache.cache_for(source);
     }
+    let groups = config.clone().groups(data);
     for source in &sources {
-        let groups = config.clone().groups(data);
-        for (key, pkgs) in groups {
-            if source.name == key {
+        for (key, pkgs) in &groups {
+            if &source.name == key {
+                if let Some(err) = cache.failure(source) {
+                    println!("{} {} — {}\n", source, "check failed".red(), err);
+                    break;
+                }
                 let pkg_count = pkgs.len();
-                let table = format!("{}", source.table(&pkgs, &cache, *all));
-                println!("{} ({} packages total)", source, pkg_count);
+                let missing_count = pkgs.iter().filter(|p| !cache.check(source, p)).count();
+                let table = format!("{}", source.table(pkgs, &cache, *all));
+                println!(
+                    "{} ({} packages total, {} missing)",
+                    source, pkg_count, missing_count
+                );
                 println!("{}", table);
                 break;
             }
@@ -38,17 +66,151 @@ pub fn status_command(config: &SantaConfig, data: &SantaData, mut cache: Package
     }
 }
 
-pub fn config_command(config: &SantaConfig, data: &SantaData, packages: bool, builtin: bool) {
-    if !builtin {
+/// Reads newline-separated package names. Blank lines and lines starting with `#` are ignored.
+pub fn read_package_list(reader: impl BufRead) -> anyhow::Result<Vec<String>> {
+    let mut packages = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            packages.push(line.to_string());
+        }
+    }
+    Ok(packages)
+}
+
+/// Adds packages to the config and saves it. A `-` is replaced with package names read from stdin.
+pub fn add_command(
+    config: &mut SantaConfig,
+    data: &SantaData,
+    config_file: &Path,
+    packages: &[String],
+    source: &Option<String>,
+) -> anyhow::Result<()> {
+    let mut names: Vec<String> = Vec::new();
+    for name in packages {
+        if name == "-" {
+            names.extend(read_package_list(std::io::stdin().lock())?);
+        } else {
+            names.push(name.to_string());
+        }
+    }
+    let packages = names;
+    let packages: Vec<String> = packages
+        .iter()
+        .map(|name| {
+            let canonical = data.canonical_for(name);
+            if &canonical != name {
+                println!("{} is an alias for {}", name, canonical);
+            }
+            canonical
+        })
+        .collect();
+    if packages.is_empty() {
+        bail!("no package specified");
+    }
+
+    let source = match source {
+        Some(name) => match data.sources.iter().find(|s| &s.name_str() == name) {
+            Some(source) => {
+                if !config.clone().source_is_enabled(source) {
+                    eprintln!(
+                        "{} {} is not enabled in your config, so packages added to it won't be installed until it is",
+                        "warning:".yellow(),
+                        source
+                    );
+                }
+                Some(source.name.clone())
+            }
+            None => bail!("unknown source '{}'", name),
+        },
+        None => None,
+    };
+
+    for package in &packages {
+        config.add_package(package, source.clone());
+    }
+    let has_comments = fs::read_to_string(config_file).map_or(false, |contents| {
+        contents
+            .lines()
+            .any(|line| line.trim_start().starts_with('#'))
+    });
+    if has_comments {
+        eprintln!(
+            "{} comments in {} aren't preserved when santa updates it",
+            "warning:".yellow(),
+            config_file.display()
+        );
+    }
+    config.save_to(config_file)?;
+    for package in &packages {
+        match &source {
+            Some(source) => println!("Added {} ({} only)", package, source),
+            None => println!("Added {}", package),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the default config to a file. An existing file is only overwritten if `force` is set.
+pub fn init_config_command(config_file: &Path, force: bool) -> anyhow::Result<()> {
+    if config_file.exists() && !force {
+        println!(
+            "Config file already exists at {}; use --force to overwrite it",
+            config_file.display()
+        );
+        return Ok(());
+    }
+    if let Some(dir) = config_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(config_file, constants::DEFAULT_CONFIG)?;
+    println!("Wrote default config to {}", config_file.display());
+    Ok(())
+}
+
+pub fn config_command(
+    config: &SantaConfig,
+    data: &SantaData,
+    packages: bool,
+    source: &Option<String>,
+    pipe: bool,
+    builtin: bool,
+) -> anyhow::Result<()> {
+    if pipe {
+        print!("{}", config.export_env());
+    } else if let Some(name) = source {
+        match data.sources.iter().find(|s| &s.name_str() == name) {
+            Some(source) => {
+                let groups = config.clone().groups(data);
+                let pkgs = groups.get(&source.name).cloned().unwrap_or_default();
+                print!("{}", serde_yaml::to_string(&pkgs).unwrap());
+            }
+            None => bail!("unknown source '{}'", name),
+        }
+    } else if !builtin {
         println!("{}", config.export());
     } else if packages {
         println!("{}", data.export());
     } else {
         println!("{}", data.sources.export())
     }
+    Ok(())
 }
 
-pub fn install_command(config: &SantaConfig, data: &SantaData, mut cache: PackageCache) {
+/// The maximum number of sources that will install packages at the same time when installing in parallel.
+const MAX_PARALLEL_INSTALLS: usize = 4;
+
+/// Installs missing packages for each enabled source. Returns an error after reporting the results if any install
+/// failed, so scripts can tell that something still needs to be done.
+pub fn install_command(
+    config: &SantaConfig,
+    data: &SantaData,
+    mut cache: PackageCache,
+    parallel: bool,
+    dry_run: bool,
+    history_file: &Path,
+) -> anyhow::Result<()> {
     // let config = config.clone();
     // filter sources to those enabled in the config
     let sources: SourceList = data
@@ -62,23 +224,286 @@ pub fn install_command(config: &SantaConfig, data: &SantaData, mut cache: Packag
     //     error!("{} {:?}", k, v);
     // }
 
+    warn_unavailable_packages(config, data, &sources);
+
     for source in &sources {
         debug!("Stats for {}", source.name);
         cache.cache_for(source);
     }
 
     // let config = config.clone();
+    let mut missing: Vec<(&PackageSource, Vec<String>)> = Vec::new();
+    let groups = config.clone().groups(data);
     for source in &sources {
-        let groups = config.clone().groups(data);
-        for (key, pkgs) in groups {
-            if source.name == key {
+        for (key, pkgs) in &groups {
+            if &source.name == key {
+                // without a successful check every package would look missing, so don't try to install them
+                if let Some(err) = cache.failure(source) {
+                    println!(
+                        "{} {} — {}; skipping it\n",
+                        source,
+                        "check failed".red(),
+                        err
+                    );
+                    break;
+                }
                 let pkgs: Vec<String> = pkgs
                     .iter()
                     .filter(|p| !cache.check(source, p))
                     .map(|p| p.to_string())
                     .collect();
-                source.exec_install(config, data, pkgs);
+                missing.push((source, pkgs));
             }
         }
     }
+
+    if dry_run {
+        print_dry_run(config, data, &missing);
+        return Ok(());
+    }
+
+    let results: Vec<(&PackageSource, InstallOutcome)> = if parallel {
+        install_in_parallel(config, data, &missing)
+    } else {
+        missing
+            .iter()
+            .map(|(source, pkgs)| (*source, source.exec_install(config, data, pkgs.to_vec())))
+            .collect()
+    };
+
+    print_install_report(&results);
+
+    let records: Vec<SourceInstallRecord> = results
+        .iter()
+        .map(|(source, outcome)| SourceInstallRecord {
+            source: source.name.clone(),
+            packages: missing
+                .iter()
+                .find(|(s, _)| s.name == source.name)
+                .map(|(_, pkgs)| pkgs.to_vec())
+                .unwrap_or_default(),
+            command: outcome.command().map(|c| c.to_string()),
+            failed_post_install_command: match outcome {
+                InstallOutcome::PostInstallFailed {
+                    post_install_command,
+                    ..
+                } => Some(post_install_command.to_string()),
+                _ => None,
+            },
+            outcome: outcome.to_string(),
+        })
+        .collect();
+    if let Err(e) = append_history(history_file, InstallHistoryEntry::new(parallel, records)) {
+        eprintln!(
+            "{} couldn't write install history to {}: {}",
+            "warning:".yellow(),
+            history_file.display(),
+            e
+        );
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, outcome)| outcome.retry_command().is_some())
+        .count();
+    if failed > 0 {
+        bail!("{} install(s) failed", failed);
+    }
+    Ok(())
+}
+
+/// Prints the command that would install the missing packages for each source, without running anything.
+fn print_dry_run(
+    config: &SantaConfig,
+    data: &SantaData,
+    missing: &[(&PackageSource, Vec<String>)],
+) {
+    for (source, pkgs) in missing {
+        match source.install_command_for(config, data, pkgs) {
+            Some(install_command) => {
+                println!(
+                    "Would install {} {} packages: {}",
+                    pkgs.len(),
+                    source,
+                    pkgs.join(", ")
+                );
+                println!("{}", install_command.bold());
+                if let Some(post) = &source.post_install_command {
+                    println!("{}", post.bold());
+                }
+                println!();
+            }
+            None => println!("No missing packages for {}", source),
+        }
+    }
+}
+
+/// Warns about configured packages that can't be installed on this host because none of the enabled sources they're
+/// available from have their package manager installed. Packages that aren't in the catalog or pinned to a source are
+/// skipped. Sources without their package manager aren't installed with, since their check fails.
+fn warn_unavailable_packages(config: &SantaConfig, data: &SantaData, sources: &SourceList) {
+    let available: Vec<&KnownSources> = sources
+        .iter()
+        .filter(|source| source.is_available())
+        .map(|source| &source.name)
+        .collect();
+    for pkg in &config.packages {
+        // pinned packages don't need to be in the catalog, e.g. when they're pinned to a custom source
+        let installable = match (config.pinned_source(pkg), data.packages.get(pkg)) {
+            (Some(pinned), _) => available.contains(&pinned),
+            (None, Some(pkg_sources)) => available
+                .iter()
+                .any(|source| pkg_sources.contains_key(source)),
+            (None, None) => continue,
+        };
+        if !installable {
+            eprintln!(
+                "{} {} can't be installed here; none of its sources are available",
+                "warning:".yellow(),
+                pkg
+            );
+        }
+    }
+}
+
+/// Installs missing packages for multiple sources concurrently. All confirmations happen before any installs start,
+/// and each source's output is buffered and printed once its install finishes.
+fn install_in_parallel<'a>(
+    config: &SantaConfig,
+    data: &SantaData,
+    missing: &[(&'a PackageSource, Vec<String>)],
+) -> Vec<(&'a PackageSource, InstallOutcome)> {
+    let mut results: Vec<(&PackageSource, InstallOutcome)> = Vec::new();
+    let mut approved: Vec<(&PackageSource, String)> = Vec::new();
+    for (source, pkgs) in missing {
+        match source.install_command_for(config, data, pkgs) {
+            Some(install_command) => {
+                if source.confirm_install(config, &install_command) {
+                    approved.push((source, install_command));
+                } else {
+                    results.push((source, source.skip_install(install_command)));
+                }
+            }
+            None => {
+                println!("No missing packages for {}", source);
+                results.push((source, InstallOutcome::NothingToInstall));
+            }
+        }
+    }
+
+    for batch in approved.chunks(MAX_PARALLEL_INSTALLS) {
+        let handles: Vec<_> = batch
+            .iter()
+            .map(|(source, install_command)| {
+                let source = (*source).clone();
+                let install_command = install_command.to_string();
+                std::thread::spawn(move || source.run_install(install_command))
+            })
+            .collect();
+        let finished: Vec<(InstallOutcome, String)> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        for ((source, _), (outcome, output)) in batch.iter().zip(finished) {
+            println!("{}", format!("{}:", source).bold());
+            println!("{}", output);
+            results.push((source, outcome));
+        }
+    }
+    results
+}
+
+/// Prints a summary of install results per source. If any installs failed, the commands needed to retry
+/// them are printed so the user can recover manually.
+fn print_install_report(results: &[(&PackageSource, InstallOutcome)]) {
+    let ran: Vec<&(&PackageSource, InstallOutcome)> = results
+        .iter()
+        .filter(|(_, outcome)| {
+            matches!(
+                outcome,
+                InstallOutcome::Succeeded { .. }
+                    | InstallOutcome::Failed { .. }
+                    | InstallOutcome::PostInstallFailed { .. }
+            )
+        })
+        .collect();
+    if ran.is_empty() {
+        return;
+    }
+
+    println!("{}", "Install summary:".bold());
+    for (source, outcome) in &ran {
+        match outcome {
+            InstallOutcome::Succeeded { .. } => println!("  {} {}", "succeeded".green(), source),
+            InstallOutcome::Failed { error, .. } => {
+                println!("  {} {} ({})", "failed".red(), source, error)
+            }
+            InstallOutcome::PostInstallFailed {
+                command,
+                post_install_command,
+                error,
+            } => {
+                println!(
+                    "  {} {} ('{}' succeeded, but '{}' failed: {})",
+                    "failed".red(),
+                    source,
+                    command,
+                    post_install_command,
+                    error
+                )
+            }
+            _ => {}
+        }
+    }
+
+    let failed: Vec<&str> = ran
+        .iter()
+        .filter_map(|(_, outcome)| outcome.retry_command())
+        .collect();
+    if !failed.is_empty() {
+        println!("\nSome installs failed. To retry them, run:");
+        for command in failed {
+            println!("{}", command.bold());
+        }
+    }
+}
+
+/// Validates the data bundled with santa, returning an error if any of it is unusable.
+pub fn self_check_command() -> anyhow::Result<()> {
+    let problems = validate_data(
+        constants::BUILTIN_PACKAGES,
+        constants::BUILTIN_SOURCES,
+        constants::DEFAULT_CONFIG,
+    );
+    for warning in &problems.warnings {
+        println!("{} {}", "warning:".yellow(), warning);
+    }
+    for error in &problems.errors {
+        println!("{} {}", "error:".red(), error);
+    }
+    if !problems.errors.is_empty() {
+        bail!("bundled data has {} error(s)", problems.errors.len());
+    }
+    println!("Bundled data is valid.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_package_list_skips_blanks_and_comments() {
+        let input = "ripgrep\n\n  # search tools\n  fd  \n#bat\nsd\n";
+        let packages = read_package_list(input.as_bytes()).unwrap();
+        assert_eq!(packages, vec!["ripgrep", "fd", "sd"]);
+    }
+
+    #[test]
+    fn read_package_list_handles_empty_input() {
+        assert!(read_package_list("".as_bytes()).unwrap().is_empty());
+        assert!(read_package_list("\n# nothing\n".as_bytes())
+            .unwrap()
+            .is_empty());
+    }
 }
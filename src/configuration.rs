@@ -3,22 +3,77 @@ use crate::sources::PackageSource;
 use crate::Exportable;
 use std::{collections::HashMap, fs, path::Path};
 
+use colored::*;
 use log::{debug, trace, warn};
 // use memoize::memoize;
 use serde::{Deserialize, Serialize};
 
 use crate::data::{constants, KnownSources, SantaData};
 
+/// The prefix for environment variables that override config values. For example, `SANTA_SOURCES=brew,cargo`
+/// overrides the `sources` config value. List values are comma-separated.
+pub static ENV_PREFIX: &str = "SANTA_";
+
+/// Returns the value of a `SANTA_`-prefixed environment variable, if it's set and not empty.
+pub fn env_override(name: &str) -> Option<String> {
+    match std::env::var(format!("{}{}", ENV_PREFIX, name)) {
+        Ok(value) if !value.trim().is_empty() => Some(value),
+        _ => None,
+    }
+}
+
+/// Returns the log level set in `SANTA_LOG_LEVEL`, from 0 (off) to 3 (trace). Invalid values are ignored with a
+/// warning.
+pub fn log_level_override() -> Option<u8> {
+    let level = env_override("LOG_LEVEL")?;
+    match level.trim().parse() {
+        Ok(level) => Some(level),
+        Err(_) => {
+            eprintln!(
+                "{} ignoring invalid {}LOG_LEVEL: {}",
+                "warning:".yellow(),
+                ENV_PREFIX,
+                level
+            );
+            None
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SantaConfig {
     pub sources: Vec<KnownSources>,
     pub packages: Vec<String>,
+    /// Sources defined by the user. A custom source with the same name as a built-in source replaces it.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub custom_sources: Option<SourceList>,
+    /// Packages that should only be installed from a specific source, regardless of source order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package_sources: Option<HashMap<String, KnownSources>>,
+    /// Sources that are temporarily turned off. They stay in `sources` so they keep their place in the source order
+    /// when they're turned back on, but they aren't checked and packages aren't installed with them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_sources: Option<Vec<KnownSources>>,
 
     #[serde(skip)]
     _groups: Option<HashMap<KnownSources, Vec<String>>>,
     #[serde(skip)]
     pub log_level: u8,
+    /// The answer to give to every confirmation prompt instead of asking, if set.
+    #[serde(skip)]
+    pub confirm_answer: Option<bool>,
+    /// Extra arguments to pass to package managers when installing.
+    #[serde(skip)]
+    pub manager_args: Vec<String>,
 }
 
 impl Default for SantaConfig {
@@ -38,19 +93,148 @@ impl SantaConfig {
     pub fn load_from(file: &Path) -> Self {
         debug!("Loading config from: {}", file.display());
         let mut yaml_str: String;
-        if file.exists() {
+        let mut config = if file.exists() {
             yaml_str = fs::read_to_string(file).unwrap();
             SantaConfig::load_from_str(&yaml_str)
         } else {
             warn!("Can't find config file: {}", file.display());
             warn!("Loading default config");
             SantaConfig::default()
+        };
+        config
+    }
+
+    /// Overrides config values with any that are set in the environment:
+    ///
+    /// - `SANTA_SOURCES`: comma-separated list of sources
+    /// - `SANTA_PACKAGES`: comma-separated list of packages
+    /// - `SANTA_PACKAGE_SOURCES`: comma-separated list of packages pinned to a source, e.g. `ripgrep=cargo`
+    /// - `SANTA_DISABLED_SOURCES`: comma-separated list of disabled sources
+    ///
+    /// Overrides only change the config that is read, so the config should be cloned first if it will be saved.
+    pub fn apply_env_overrides(&mut self) {
+        self.apply_overrides(env_override);
+    }
+
+    /// Overrides config values using a function that returns the value of an override by name, e.g. `SOURCES`.
+    fn apply_overrides(&mut self, lookup: impl Fn(&str) -> Option<String>) {
+        if let Some(sources) = lookup("SOURCES") {
+            debug!("Overriding sources from environment: {}", sources);
+            self.sources = split_list(&sources)
+                .iter()
+                .map(|name| name.parse().unwrap())
+                .collect();
+            self._groups = None;
+        }
+        if let Some(packages) = lookup("PACKAGES") {
+            debug!("Overriding packages from environment: {}", packages);
+            self.packages = split_list(&packages);
+            self._groups = None;
+        }
+        if let Some(pins) = lookup("PACKAGE_SOURCES") {
+            debug!("Overriding package sources from environment: {}", pins);
+            let mut package_sources = HashMap::new();
+            for pin in split_list(&pins) {
+                match pin.split_once('=') {
+                    Some((package, source)) => {
+                        package_sources
+                            .insert(package.trim().to_string(), source.trim().parse().unwrap());
+                    }
+                    None => warn!("Ignoring package source without a source: {}", pin),
+                }
+            }
+            self.package_sources = Some(package_sources);
+            self._groups = None;
+        }
+        if let Some(disabled) = lookup("DISABLED_SOURCES") {
+            debug!("Overriding disabled sources from environment: {}", disabled);
+            self.disabled_sources = Some(
+                split_list(&disabled)
+                    .iter()
+                    .map(|name| name.parse().unwrap())
+                    .collect(),
+            );
+            self._groups = None;
+        }
+    }
+
+    /// Returns the config as shell `export` statements. The variables are the same ones santa reads from the
+    /// environment, so sourcing the output reproduces this config. Custom sources can't be set in the environment, so
+    /// they aren't included.
+    pub fn export_env(&self) -> String {
+        let sources: Vec<String> = self.sources.iter().map(|s| s.to_string()).collect();
+        let mut pins: Vec<String> = self
+            .package_sources
+            .iter()
+            .flatten()
+            .map(|(package, source)| format!("{}={}", package, source))
+            .collect();
+        pins.sort();
+        let disabled: Vec<String> = self
+            .disabled_sources
+            .iter()
+            .flatten()
+            .map(|s| s.to_string())
+            .collect();
+        let vars = [
+            ("SOURCES", sources.join(",")),
+            ("PACKAGES", self.packages.join(",")),
+            ("PACKAGE_SOURCES", pins.join(",")),
+            ("DISABLED_SOURCES", disabled.join(",")),
+            ("LOG_LEVEL", self.log_level.to_string()),
+        ];
+        vars.iter()
+            .map(|(name, value)| {
+                format!(
+                    "export {}{}='{}'\n",
+                    ENV_PREFIX,
+                    name,
+                    value.replace('\'', "'\\''")
+                )
+            })
+            .collect()
+    }
+
+    /// Writes the config to a file, creating its parent directory if needed. The file is rewritten from the config
+    /// values, so any comments in an existing file are lost.
+    pub fn save_to(&self, file: &Path) -> anyhow::Result<()> {
+        debug!("Saving config to: {}", file.display());
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
         }
+        fs::write(file, self.export())?;
+        Ok(())
+    }
+
+    /// Adds a package to the config. If a source is provided, the package will only be installed from that
+    /// source.
+    pub fn add_package(&mut self, package: &str, source: Option<KnownSources>) {
+        if !self.packages.iter().any(|p| p == package) {
+            self.packages.push(package.to_string());
+        }
+        if let Some(source) = source {
+            self.package_sources
+                .get_or_insert_with(HashMap::new)
+                .insert(package.to_string(), source);
+        }
+        self._groups = None;
+    }
+
+    /// Returns the source a package is pinned to, if any.
+    pub fn pinned_source(&self, package: &str) -> Option<&KnownSources> {
+        self.package_sources.as_ref()?.get(package)
+    }
+
+    /// Returns true if a source is listed in `disabled_sources`.
+    pub fn source_is_disabled(&self, source: &KnownSources) -> bool {
+        self.disabled_sources
+            .as_ref()
+            .map_or(false, |disabled| disabled.contains(source))
     }
 
     pub fn source_is_enabled(self, source: &PackageSource) -> bool {
         trace!("Checking if {} is enabled", source);
-        return self.sources.contains(&source.name);
+        return self.sources.contains(&source.name) && !self.source_is_disabled(&source.name);
     }
 
     /// Groups the configured (enabled) packages by source.
@@ -58,7 +242,13 @@ impl SantaConfig {
         match &self._groups {
             Some(groups) => groups.clone(),
             None => {
-                let configured_sources: Vec<KnownSources> = self.sources;
+                // skip disabled sources so their packages fall through to the next source
+                let configured_sources: Vec<KnownSources> = self
+                    .sources
+                    .iter()
+                    .filter(|name| !self.source_is_disabled(name))
+                    .cloned()
+                    .collect();
                 // let s2 = self.sources.clone();
                 let mut groups: HashMap<KnownSources, Vec<String>> = HashMap::new();
                 for source in configured_sources.clone() {
@@ -66,6 +256,19 @@ impl SantaConfig {
                 }
 
                 for pkg in &self.packages {
+                    if let Some(source) = self.pinned_source(pkg) {
+                        match groups.get_mut(source) {
+                            Some(v) => {
+                                trace!("Adding {} to {} list (pinned).", pkg, source);
+                                v.push(pkg.to_string());
+                            }
+                            None => {
+                                warn!("{} is pinned to {}, which is not enabled", pkg, source);
+                            }
+                        }
+                        continue;
+                    }
+
                     for source in configured_sources.clone() {
                         if data.packages.contains_key(pkg) {
                             let available_sources = data.packages.get(pkg).unwrap();
@@ -93,3 +296,79 @@ impl SantaConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "sources: [brew, cargo]\npackages: [bat, ripgrep]\n";
+
+    fn overrides(values: &[(&str, &str)]) -> HashMap<String, String> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Parses the output of `export_env` into variable values, without the prefix.
+    fn parse_exports(exports: &str) -> HashMap<String, String> {
+        exports
+            .lines()
+            .map(|line| {
+                let (name, value) = line
+                    .strip_prefix("export SANTA_")
+                    .unwrap()
+                    .split_once('=')
+                    .unwrap();
+                let value = value.trim_matches('\'').replace("'\\''", "'");
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exports_round_trip_through_overrides() {
+        let mut config = SantaConfig::load_from_str(
+            "sources: [brew, mine]\npackages: [bat, ripgrep, \"it's\"]\npackage_sources:\n  ripgrep: mine\n  bat: brew\ndisabled_sources: [brew]\n",
+        );
+        config.log_level = 2;
+        let exports = config.export_env();
+        assert!(exports.contains("export SANTA_PACKAGE_SOURCES='bat=brew,ripgrep=mine'\n"));
+        assert!(exports.contains("export SANTA_LOG_LEVEL='2'\n"));
+
+        let values = parse_exports(&exports);
+        let mut loaded = SantaConfig::load_from_str("sources: [apt]\npackages: [fd]\n");
+        loaded.apply_overrides(|name| values.get(name).cloned().filter(|v| !v.is_empty()));
+        assert_eq!(loaded.sources, config.sources);
+        assert_eq!(loaded.packages, config.packages);
+        assert_eq!(loaded.package_sources, config.package_sources);
+        assert_eq!(loaded.disabled_sources, config.disabled_sources);
+    }
+
+    #[test]
+    fn overrides_replace_config_values() {
+        let mut config = SantaConfig::load_from_str(CONFIG);
+        let values = overrides(&[("SOURCES", "cargo, mine"), ("PACKAGES", "fd,,sd")]);
+        config.apply_overrides(|name| values.get(name).cloned());
+        assert_eq!(
+            config.sources,
+            vec![
+                KnownSources::Cargo,
+                KnownSources::Custom("mine".to_string())
+            ]
+        );
+        assert_eq!(config.packages, vec!["fd", "sd"]);
+    }
+
+    #[test]
+    fn unset_overrides_keep_config_values() {
+        let mut config = SantaConfig::load_from_str(CONFIG);
+        let values = overrides(&[("PACKAGES", "fd")]);
+        config.apply_overrides(|name| values.get(name).cloned());
+        assert_eq!(
+            config.sources,
+            vec![KnownSources::Brew, KnownSources::Cargo]
+        );
+        assert_eq!(config.packages, vec!["fd"]);
+    }
+}
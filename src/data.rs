@@ -1,3 +1,4 @@
+use crate::configuration::{env_override, ENV_PREFIX};
 use crate::SantaConfig;
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet},
@@ -5,6 +6,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use anyhow::{anyhow, bail};
+use colored::*;
+use once_cell::sync::Lazy;
+
 // extern crate yaml_rust;
 use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
@@ -25,8 +30,10 @@ pub enum KnownSources {
     Pacman,
     Scoop,
     Nix,
+    /// Any source that santa doesn't know about, such as a user-defined custom source. The name is kept as-is
+    /// so it round-trips through serialization.
     #[serde(other)]
-    Unknown(String),
+    Custom(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
@@ -83,8 +90,58 @@ impl std::fmt::Display for Platform {
     }
 }
 
+impl std::str::FromStr for Platform {
+    type Err = anyhow::Error;
+
+    /// Parses a platform in the form `os/arch` or `os/arch/distro`, e.g. `linux/x64` or `linux/x64/ubuntu`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<String> = s.split('/').map(|p| p.trim().to_lowercase()).collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            bail!("expected a platform like 'linux/x64', got '{}'", s);
+        }
+        let os = match parts[0].as_str() {
+            "macos" => OS::Macos,
+            "linux" => OS::Linux,
+            "windows" => OS::Windows,
+            other => bail!("unknown OS '{}'; expected macos, linux, or windows", other),
+        };
+        let arch = match parts[1].as_str() {
+            "x64" => Arch::X64,
+            "aarch64" => Arch::Aarch64,
+            other => bail!("unknown architecture '{}'; expected x64 or aarch64", other),
+        };
+        let distro = match parts.get(2).map(|d| d.as_str()) {
+            None => None,
+            Some("none") => Some(Distro::None),
+            Some("archlinux") => Some(Distro::ArchLinux),
+            Some("ubuntu") => Some(Distro::Ubuntu),
+            Some(other) => bail!("unknown distro '{}'; expected archlinux or ubuntu", other),
+        };
+        Ok(Platform { os, arch, distro })
+    }
+}
+
 impl Platform {
-    pub fn current() -> Self {
+    /// Returns the platform set in the `SANTA_PLATFORM` environment variable, if any. Invalid values are ignored with
+    /// a warning.
+    pub fn from_env_override() -> Option<Platform> {
+        let value = env_override("PLATFORM")?;
+        match value.parse() {
+            Ok(platform) => Some(platform),
+            Err(e) => {
+                eprintln!(
+                    "{} ignoring invalid {}PLATFORM: {}",
+                    "warning:".yellow(),
+                    ENV_PREFIX,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns the platform santa is running on, or `None` if its architecture isn't one that platforms can describe.
+    pub fn current() -> Option<Self> {
         let family = std::env::consts::FAMILY;
         let os = std::env::consts::OS;
         let arch = std::env::consts::ARCH;
@@ -108,10 +165,10 @@ impl Platform {
         match arch {
             "x86_64" => platform.arch = Arch::X64,
             "aarch64" => platform.arch = Arch::Aarch64,
-            _ => todo!(),
+            _ => return None,
         }
 
-        platform
+        Some(platform)
     }
 }
 
@@ -145,6 +202,10 @@ pub struct PackageData {
     pub pre: Option<String>,
     /// A string to postpend to the install string
     pub post: Option<String>,
+    /// Other names that should resolve to this package. Aliases are listed under a source entry, but they resolve to
+    /// the package for every source, so they only need to be listed once. A source entry that is otherwise empty
+    /// (`cargo: ~`) has to be written as a mapping to list aliases, e.g. `cargo: { aliases: [rg] }`.
+    pub aliases: Option<Vec<String>>,
     // Sources that can install this package
     // pub sources: Option<Vec<String>>,
 }
@@ -157,6 +218,7 @@ impl PackageData {
             after: None,
             pre: None,
             post: None,
+            aliases: None,
             // sources: None,
         }
     }
@@ -224,18 +286,83 @@ impl SantaData {
         SantaData { packages, sources }
     }
 
-    // pub fn update_from_config(&mut self, config: &SantaConfig) {
+    /// Adds the custom sources defined in a config. A custom source with the same name as a built-in source
+    /// replaces it, so users can change a built-in source, e.g. to add a `post_install_command`.
+    pub fn update_from_config(&mut self, config: &SantaConfig) {
+        if let Some(custom_sources) = &config.custom_sources {
+            for custom in custom_sources {
+                match self.sources.iter_mut().find(|s| s.name == custom.name) {
+                    Some(source) => {
+                        debug!(
+                            "Replacing built-in source {} with custom source",
+                            source.name
+                        );
+                        *source = custom.clone();
+                    }
+                    None => {
+                        debug!("Adding custom source {}", custom.name);
+                        self.sources.push(custom.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets the platform that the sources' commands are resolved for.
+    pub fn set_platform(&mut self, platform: &Option<Platform>) {
+        for source in &mut self.sources {
+            source.platform = platform.clone();
+        }
+    }
+
+    /// Returns the sources that aren't disabled in the config. If the config lists sources, only those are returned.
     pub fn sources(&self, config: &SantaConfig) -> SourceList {
+        let sources = self
+            .sources
+            .iter()
+            .filter(|source| !config.source_is_disabled(&source.name));
         if config.sources.is_empty() {
-            self.sources.clone()
+            sources.cloned().collect()
         } else {
-            let mut ret: SourceList = self.sources.clone();
-            ret.extend(self.sources.clone());
-            ret
+            sources
+                .filter(|source| config.clone().source_is_enabled(source))
+                .cloned()
+                .collect()
         }
     }
 
-    pub fn name_for(&self, package: &str, source: &PackageSource) -> String {
+    /// Returns the canonical package name for a name that might be an alias. Aliases include the `aliases` and
+    /// source-specific `name` listed under any of a package's sources. Names that are already canonical, or that
+    /// aren't known, are returned unchanged.
+    pub fn canonical_for(&self, name: &str) -> String {
+        if self.packages.contains_key(name) {
+            return name.to_string();
+        }
+        let mut matches: Vec<&String> = self
+            .packages
+            .iter()
+            .filter(|(_, sources)| {
+                sources.values().flatten().any(|data| {
+                    data.name.as_deref() == Some(name)
+                        || data
+                            .aliases
+                            .as_ref()
+                            .map_or(false, |aliases| aliases.iter().any(|a| a == name))
+                })
+            })
+            .map(|(pkg, _)| pkg)
+            .collect();
+        // sort so the result is stable if more than one package claims the alias
+        matches.sort();
+        match matches.first() {
+            Some(pkg) => pkg.to_string(),
+            None => name.to_string(),
+        }
+    }
+
+    /// Returns the name a source uses for a package. This is the source-specific name from the package data if
+    /// there is one; otherwise it's the package name adjusted for the source (e.g. with a prefix prepended).
+    pub fn resolve_name(&self, package: &str, source: &PackageSource) -> String {
         match self.packages.get(package) {
             #[allow(clippy::collapsible_match)]
             Some(sources) => match sources.get(&source.name) {
@@ -254,9 +381,126 @@ impl SantaData {
     }
 }
 
+static BUILTIN_CATALOG: Lazy<Result<PackageDataList, String>> =
+    Lazy::new(|| serde_yaml::from_str(constants::BUILTIN_PACKAGES).map_err(|e| e.to_string()));
+
+static BUILTIN_SOURCES: Lazy<Result<SourceList, String>> =
+    Lazy::new(|| serde_yaml::from_str(constants::BUILTIN_SOURCES).map_err(|e| e.to_string()));
+
+/// Returns the package catalog that is bundled with santa. It's parsed the first time it's needed.
+pub fn builtin_catalog() -> anyhow::Result<&'static PackageDataList> {
+    BUILTIN_CATALOG
+        .as_ref()
+        .map_err(|e| anyhow!("bundled package data doesn't parse: {}", e))
+}
+
+/// Returns the source definitions that are bundled with santa. They're parsed the first time they're needed.
+pub fn builtin_sources() -> anyhow::Result<&'static SourceList> {
+    BUILTIN_SOURCES
+        .as_ref()
+        .map_err(|e| anyhow!("bundled source data doesn't parse: {}", e))
+}
+
+/// Problems found when validating santa data.
+#[derive(Debug, Default)]
+pub struct DataProblems {
+    /// Problems that make the data unusable.
+    pub errors: Vec<String>,
+    /// Problems that don't prevent santa from working, such as references to sources that aren't defined.
+    pub warnings: Vec<String>,
+}
+
+/// Validates package, source, and config data. Each must parse, and the config and package data should only
+/// reference sources that are defined.
+pub fn validate_data(packages_str: &str, sources_str: &str, config_str: &str) -> DataProblems {
+    let mut problems = DataProblems::default();
+
+    let packages: Option<PackageDataList> = match serde_yaml::from_str(packages_str) {
+        Ok(packages) => Some(packages),
+        Err(e) => {
+            problems
+                .errors
+                .push(format!("package data doesn't parse: {}", e));
+            None
+        }
+    };
+    let sources: Option<SourceList> = match serde_yaml::from_str(sources_str) {
+        Ok(sources) => Some(sources),
+        Err(e) => {
+            problems
+                .errors
+                .push(format!("source data doesn't parse: {}", e));
+            None
+        }
+    };
+    let config: Option<SantaConfig> = match serde_yaml::from_str(config_str) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            problems
+                .errors
+                .push(format!("default config doesn't parse: {}", e));
+            None
+        }
+    };
+
+    let sources = match sources {
+        Some(sources) => sources,
+        None => return problems,
+    };
+    let mut defined: HashSet<KnownSources> = HashSet::new();
+    for source in &sources {
+        if !defined.insert(source.name.clone()) {
+            problems.errors.push(format!(
+                "source '{}' is defined more than once",
+                source.name
+            ));
+        }
+    }
+
+    if let Some(config) = config {
+        for source in &config.sources {
+            if !defined.contains(source) {
+                problems.errors.push(format!(
+                    "default config uses source '{}', which isn't defined",
+                    source
+                ));
+            }
+        }
+    }
+
+    if let Some(packages) = packages {
+        let mut undefined: HashMap<String, Vec<String>> = HashMap::new();
+        for (pkg, pkg_sources) in &packages {
+            for source in pkg_sources.keys() {
+                if !defined.contains(source) {
+                    undefined
+                        .entry(source.to_string())
+                        .or_default()
+                        .push(pkg.to_string());
+                }
+            }
+        }
+        let mut undefined: Vec<(String, Vec<String>)> = undefined.into_iter().collect();
+        undefined.sort();
+        for (source, mut pkgs) in undefined {
+            pkgs.sort();
+            problems.warnings.push(format!(
+                "packages reference source '{}', which isn't defined: {}",
+                source,
+                pkgs.join(", ")
+            ));
+        }
+    }
+
+    problems
+}
+
 impl Default for SantaData {
     fn default() -> Self {
-        SantaData::load_from_str(constants::BUILTIN_PACKAGES, constants::BUILTIN_SOURCES)
+        SantaData {
+            packages: builtin_catalog().unwrap().clone(),
+            sources: builtin_sources().unwrap().clone(),
+        }
     }
 }
 
@@ -268,3 +512,228 @@ impl Exportable for SantaData {
         serde_yaml::to_string(&self).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCES: &str = r#"
+- name: brew
+  emoji: 🍺
+  shell_command: brew
+  install_command: "brew install"
+  check_command: "brew leaves --installed-on-request"
+- name: cargo
+  emoji: 📦
+  shell_command: cargo
+  install_command: "cargo install"
+  check_command: "cargo install --list"
+- name: nix
+  emoji: 📈
+  shell_command: nix-env
+  install_command: "nix-env -iA"
+  check_command: "nix-env -q"
+  prepend_to_package_name: "nixpkgs."
+"#;
+
+    const PACKAGES: &str = r#"
+ripgrep:
+  brew:
+    name: rg
+  cargo:
+  nix:
+bat:
+  cargo:
+fd:
+  cargo:
+    name: fd-find
+  brew:
+    aliases: [find-fd]
+"#;
+
+    const CONFIG: &str = "sources: [brew, cargo]\npackages: [ripgrep]\n";
+
+    fn config(yaml: &str) -> SantaConfig {
+        SantaConfig::load_from_str(yaml)
+    }
+
+    #[test]
+    fn custom_source_names_round_trip() {
+        let custom: KnownSources = "mysource".parse().unwrap();
+        assert_eq!(custom, KnownSources::Custom("mysource".to_string()));
+        assert_eq!(custom.to_string(), "mysource");
+
+        let yaml = serde_yaml::to_string(&vec![custom.clone(), KnownSources::Brew]).unwrap();
+        let parsed: Vec<KnownSources> = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, vec![custom, KnownSources::Brew]);
+    }
+
+    #[test]
+    fn builtin_source_names_are_not_custom() {
+        let brew: KnownSources = "brew".parse().unwrap();
+        assert_eq!(brew, KnownSources::Brew);
+        assert_eq!(brew.to_string(), "brew");
+    }
+
+    #[test]
+    fn builtin_catalog_is_parsed_once() {
+        let catalog = builtin_catalog().unwrap();
+        assert!(!catalog.is_empty());
+        assert!(catalog.contains_key("ripgrep"));
+        assert!(std::ptr::eq(catalog, builtin_catalog().unwrap()));
+
+        let sources = builtin_sources().unwrap();
+        assert!(sources.iter().any(|s| s.name == KnownSources::Brew));
+        assert!(std::ptr::eq(sources, builtin_sources().unwrap()));
+    }
+
+    #[test]
+    fn default_data_uses_the_builtin_catalog() {
+        let data = SantaData::default();
+        assert_eq!(data.packages.len(), builtin_catalog().unwrap().len());
+        assert_eq!(&data.sources, builtin_sources().unwrap());
+    }
+
+    #[test]
+    fn bundled_data_is_valid() {
+        let problems = validate_data(
+            constants::BUILTIN_PACKAGES,
+            constants::BUILTIN_SOURCES,
+            constants::DEFAULT_CONFIG,
+        );
+        assert!(problems.errors.is_empty(), "{:?}", problems.errors);
+    }
+
+    #[test]
+    fn validate_data_reports_data_that_does_not_parse() {
+        let corrupted = SOURCES.replace("emoji: 📦", "emoji: [📦");
+        let problems = validate_data("ripgrep: [brew", &corrupted, CONFIG);
+        assert_eq!(problems.errors.len(), 2);
+        assert!(problems.errors[0].starts_with("package data doesn't parse"));
+        assert!(problems.errors[1].starts_with("source data doesn't parse"));
+    }
+
+    #[test]
+    fn validate_data_reports_undefined_and_duplicate_sources() {
+        let sources = format!(
+            "{}{}",
+            SOURCES,
+            &SOURCES[..SOURCES.find("- name: cargo").unwrap()]
+        );
+        let problems = validate_data(PACKAGES, &sources, "sources: [brew, apt]\npackages: []\n");
+        assert_eq!(
+            problems.errors,
+            vec![
+                "source 'brew' is defined more than once",
+                "default config uses source 'apt', which isn't defined",
+            ]
+        );
+
+        let problems = validate_data("fd:\n  apt:\n  brew:\n", SOURCES, CONFIG);
+        assert!(problems.errors.is_empty());
+        assert_eq!(
+            problems.warnings,
+            vec!["packages reference source 'apt', which isn't defined: fd"]
+        );
+    }
+
+    fn source(data: &SantaData, name: KnownSources) -> &PackageSource {
+        data.sources.iter().find(|s| s.name == name).unwrap()
+    }
+
+    #[test]
+    fn canonical_for_resolves_aliases() {
+        let data = SantaData::load_from_str(PACKAGES, SOURCES);
+        assert_eq!(data.canonical_for("rg"), "ripgrep");
+        assert_eq!(data.canonical_for("fd-find"), "fd");
+        assert_eq!(data.canonical_for("find-fd"), "fd");
+    }
+
+    #[test]
+    fn aliases_listed_under_one_source_apply_to_every_source() {
+        let data = SantaData::load_from_str(PACKAGES, SOURCES);
+        // find-fd is listed under brew, but resolves to the package that cargo installs as fd-find
+        let package = data.canonical_for("find-fd");
+        let cargo = source(&data, KnownSources::Cargo);
+        assert_eq!(data.resolve_name(&package, cargo), "fd-find");
+    }
+
+    #[test]
+    fn canonical_for_leaves_other_names_unchanged() {
+        let data = SantaData::load_from_str(PACKAGES, SOURCES);
+        assert_eq!(data.canonical_for("ripgrep"), "ripgrep");
+        assert_eq!(data.canonical_for("unknown"), "unknown");
+    }
+
+    #[test]
+    fn resolve_name_uses_source_specific_names() {
+        let data = SantaData::load_from_str(PACKAGES, SOURCES);
+        let brew = source(&data, KnownSources::Brew);
+        assert_eq!(data.resolve_name("ripgrep", brew), "rg");
+    }
+
+    #[test]
+    fn resolve_name_falls_back_to_the_package_name() {
+        let data = SantaData::load_from_str(PACKAGES, SOURCES);
+        let cargo = source(&data, KnownSources::Cargo);
+        assert_eq!(data.resolve_name("ripgrep", cargo), "ripgrep");
+        assert_eq!(data.resolve_name("unknown", cargo), "unknown");
+
+        let nix = source(&data, KnownSources::Nix);
+        assert_eq!(data.resolve_name("ripgrep", nix), "nixpkgs.ripgrep");
+        assert_eq!(data.resolve_name("unknown", nix), "nixpkgs.unknown");
+    }
+
+    #[test]
+    fn custom_sources_replace_or_extend_builtin_sources() {
+        let mut data = SantaData::load_from_str(PACKAGES, SOURCES);
+        let config = config(
+            r#"
+sources: [brew, mine]
+packages: []
+custom_sources:
+  - name: brew
+    emoji: 🍺
+    shell_command: brew
+    install_command: "brew install"
+    check_command: "brew list"
+    post_install_command: "brew cleanup"
+  - name: mine
+    emoji: 🧪
+    shell_command: mine
+    install_command: "mine add"
+    check_command: "mine list"
+"#,
+        );
+        data.update_from_config(&config);
+
+        let names: Vec<String> = data.sources.iter().map(|s| s.name_str()).collect();
+        assert_eq!(names, vec!["brew", "cargo", "nix", "mine"]);
+        let brew = &data.sources[0];
+        assert_eq!(brew.check_command(), "brew list");
+        assert_eq!(brew.post_install_command.as_deref(), Some("brew cleanup"));
+        assert_eq!(
+            data.sources[3].name,
+            KnownSources::Custom("mine".to_string())
+        );
+    }
+
+    #[test]
+    fn disabled_sources_are_skipped_but_kept_in_the_config() {
+        let data = SantaData::load_from_str(PACKAGES, SOURCES);
+        let config = config(
+            "sources: [brew, cargo, nix]\npackages: [ripgrep, bat]\ndisabled_sources: [brew]\n",
+        );
+        let names: Vec<KnownSources> = data.sources(&config).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec![KnownSources::Cargo, KnownSources::Nix]);
+        assert_eq!(
+            config.sources,
+            vec![KnownSources::Brew, KnownSources::Cargo, KnownSources::Nix]
+        );
+
+        // ripgrep falls through to the next source in order
+        let groups = config.groups(&data);
+        assert!(!groups.contains_key(&KnownSources::Brew));
+        assert_eq!(groups[&KnownSources::Cargo], vec!["ripgrep", "bat"]);
+    }
+}
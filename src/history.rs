@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::data::KnownSources;
+
+/// The name of the install history file, which is stored alongside the config file.
+pub static HISTORY_FILE_NAME: &str = "history.yaml";
+
+/// The maximum number of entries kept in the history file. Older entries are dropped when new ones are added.
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// What happened when installing packages from a single source.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SourceInstallRecord {
+    pub source: KnownSources,
+    pub packages: Vec<String>,
+    /// The install command, if one was needed.
+    pub command: Option<String>,
+    /// The source's post-install command, if it ran and failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_post_install_command: Option<String>,
+    /// One of `nothing to install`, `skipped`, `succeeded`, `failed`, or `post-install failed`.
+    pub outcome: String,
+}
+
+/// A record of a single `santa install` run.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstallHistoryEntry {
+    /// When the install ran, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Whether sources were installed in parallel.
+    pub parallel: bool,
+    pub sources: Vec<SourceInstallRecord>,
+}
+
+impl InstallHistoryEntry {
+    pub fn new(parallel: bool, sources: Vec<SourceInstallRecord>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        InstallHistoryEntry {
+            timestamp,
+            parallel,
+            sources,
+        }
+    }
+}
+
+pub type InstallHistory = Vec<InstallHistoryEntry>;
+
+/// Adds an entry to the end of the history file, creating the file if needed. Only the most recent
+/// `MAX_HISTORY_ENTRIES` entries are kept.
+pub fn append_history(file: &Path, entry: InstallHistoryEntry) -> anyhow::Result<()> {
+    let mut history: InstallHistory = if file.exists() {
+        serde_yaml::from_str(&fs::read_to_string(file)?)?
+    } else {
+        Vec::new()
+    };
+    history.push(entry);
+    if history.len() > MAX_HISTORY_ENTRIES {
+        let excess = history.len() - MAX_HISTORY_ENTRIES;
+        history.drain(..excess);
+    }
+
+    debug!("Writing install history to: {}", file.display());
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, serde_yaml::to_string(&history)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(outcome: &str) -> SourceInstallRecord {
+        SourceInstallRecord {
+            source: KnownSources::Cargo,
+            packages: vec!["bat".to_string(), "sd".to_string()],
+            command: Some("cargo install bat sd".to_string()),
+            failed_post_install_command: None,
+            outcome: outcome.to_string(),
+        }
+    }
+
+    fn read(file: &Path) -> InstallHistory {
+        serde_yaml::from_str(&fs::read_to_string(file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn append_history_writes_entry_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("santa").join(HISTORY_FILE_NAME);
+        append_history(
+            &file,
+            InstallHistoryEntry::new(true, vec![record("succeeded")]),
+        )
+        .unwrap();
+
+        let history = read(&file);
+        assert_eq!(history.len(), 1);
+        let entry = &history[0];
+        assert!(entry.timestamp > 0);
+        assert!(entry.parallel);
+        assert_eq!(entry.sources.len(), 1);
+        let source = &entry.sources[0];
+        assert_eq!(source.source, KnownSources::Cargo);
+        assert_eq!(source.packages, vec!["bat", "sd"]);
+        assert_eq!(source.command.as_deref(), Some("cargo install bat sd"));
+        assert_eq!(source.outcome, "succeeded");
+    }
+
+    #[test]
+    fn append_history_keeps_the_most_recent_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(HISTORY_FILE_NAME);
+        for i in 0..MAX_HISTORY_ENTRIES + 5 {
+            let mut entry = InstallHistoryEntry::new(false, vec![record("skipped")]);
+            entry.timestamp = i as u64;
+            append_history(&file, entry).unwrap();
+        }
+
+        let history = read(&file);
+        assert_eq!(history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history[0].timestamp, 5);
+        assert_eq!(
+            history.last().unwrap().timestamp,
+            (MAX_HISTORY_ENTRIES + 4) as u64
+        );
+    }
+}
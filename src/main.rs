@@ -19,13 +19,14 @@ use directories::BaseDirs;
 use std::path::{Path, PathBuf};
 
 use crate::commands::*;
-use crate::data::SantaData;
+use crate::data::{Platform, SantaData};
 use crate::sources::PackageCache;
 use crate::traits::Exportable;
 
 mod commands;
 mod configuration;
 mod data;
+mod history;
 mod sources;
 mod traits;
 
@@ -37,6 +38,7 @@ mod traits;
 // }
 
 static DEFAULT_CONFIG_FILE_PATH: &str = ".config/santa/config.yaml";
+static CONFIG_DIR_PATH: &str = ".config/santa";
 
 /// Manage default sets of packages for a variety of package managers.
 #[derive(Parser)]
@@ -53,21 +55,81 @@ struct Cli {
     /// Increase logging level
     #[clap(short, long, global = true, action = ArgAction::Count)]
     verbose: u8,
+
+    /// Answer yes to all confirmation prompts
+    #[clap(
+        short,
+        long,
+        global = true,
+        alias = "no-confirm",
+        conflicts_with = "no"
+    )]
+    yes: bool,
+
+    /// Answer no to all confirmation prompts
+    #[clap(long, global = true)]
+    no: bool,
+
+    /// Disable colored output; setting the NO_COLOR environment variable does the same
+    #[clap(long, global = true)]
+    no_color: bool,
+
+    /// Use the config file for a profile, i.e. `~/.config/santa/<PROFILE>.yaml`
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
+    /// Resolve source commands for another platform, e.g. `linux/x64` or `windows/x64`
+    #[clap(long, global = true)]
+    platform: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Validates the data bundled with santa
+    #[clap(hide = true)]
+    SelfCheck,
     /// Prints the status of santa packages
     Status {
         /// List all packages, not just missing ones
         #[clap(short, long)]
         all: bool,
+
+        /// Only show these sources; may be repeated or comma-separated
+        #[clap(short, long, value_delimiter = ',')]
+        source: Vec<String>,
+
+        /// Only show sources whose package manager is installed
+        #[clap(long)]
+        available_only: bool,
     },
     /// Installs packages
-    Install { source: Option<String> },
+    ///
+    /// Exits with an error if any install fails, after printing the commands needed to retry it.
+    Install {
+        source: Option<String>,
+
+        /// Install from multiple sources at the same time
+        #[clap(long)]
+        parallel: bool,
+
+        /// Extra arguments to pass to the package manager, e.g. "--no-quarantine"
+        #[clap(long, allow_hyphen_values = true)]
+        manager_args: Option<String>,
+
+        /// Print the commands that would be run without running them
+        #[clap(long)]
+        dry_run: bool,
+    },
     /// Adds a package to the tracking list for a package source
+    ///
+    /// The config file is rewritten when packages are added, so any comments in it are lost.
     Add {
-        package: Option<String>,
+        /// The packages to add; `-` is replaced with package names read from stdin
+        #[clap(value_name = "PACKAGE")]
+        packages: Vec<String>,
+
+        /// Only install the packages from this source
+        #[clap(short, long)]
         source: Option<String>,
     },
     Config {
@@ -75,20 +137,56 @@ enum Commands {
         #[clap(short, long)]
         packages: bool,
 
+        /// Only show packages configured for this source
+        #[clap(short, long, requires = "packages")]
+        source: Option<String>,
+
         // #[clap(short, long)]
         // packages: bool,
 
         // #[clap(short, long)]
         // local: bool,
+        /// Print the config as shell exports that can be sourced, e.g. `eval "$(santa config --pipe)"`
         #[clap(long)]
         pipe: bool,
+
+        /// Write the default config file if it doesn't exist
+        #[clap(long)]
+        init: bool,
+
+        /// Overwrite an existing config file when using --init
+        #[clap(long, requires = "init")]
+        force: bool,
     },
 }
 
-fn load_config(path: &Path) -> SantaConfig {
+fn config_file_path(path: &Path) -> PathBuf {
     let dir = BaseDirs::new().unwrap();
     let home_dir = dir.home_dir();
-    let config_file = home_dir.join(path);
+    home_dir.join(path)
+}
+
+/// Returns the path to the config file, relative to the home directory, for a profile. Without a profile, the
+/// default config file is used.
+fn profile_config_path(profile: &Option<String>) -> anyhow::Result<PathBuf> {
+    match profile {
+        Some(name) => {
+            if name.is_empty() || name.contains(|c| c == '/' || c == '\\' || c == '.') {
+                bail!("invalid profile name '{}'", name);
+            }
+            // santa keeps other files next to the config files
+            let history_stem = Path::new(history::HISTORY_FILE_NAME).file_stem();
+            if history_stem == Some(name.as_ref()) {
+                bail!("'{}' is reserved and can't be used as a profile name", name);
+            }
+            Ok(Path::new(CONFIG_DIR_PATH).join(format!("{}.yaml", name)))
+        }
+        None => Ok(PathBuf::from(DEFAULT_CONFIG_FILE_PATH)),
+    }
+}
+
+fn load_config(path: &Path) -> SantaConfig {
+    let config_file = config_file_path(path);
     let config = SantaConfig::load_from(&config_file);
     trace!("{:?}", config);
     config
@@ -97,55 +195,142 @@ fn load_config(path: &Path) -> SantaConfig {
 pub fn run() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
 
+    // colored honors NO_COLOR on its own, but lets CLICOLOR_FORCE take precedence over it
+    let no_color_env = env::var_os("NO_COLOR").map_or(false, |value| !value.is_empty());
+    if cli.no_color || no_color_env {
+        colored::control::set_override(false);
+    }
+
     let mut log_level = LevelFilter::Info;
 
-    match &cli.verbose {
+    // the log level can also be set in the environment, but the CLI flag takes precedence
+    let verbose = match cli.verbose {
+        0 => configuration::log_level_override().unwrap_or(0),
+        verbose => verbose,
+    };
+
+    match &verbose {
         1 => log_level = LevelFilter::Info,
         2 => log_level = LevelFilter::Debug,
         3 => log_level = LevelFilter::Trace,
         _ => log_level = LevelFilter::Off,
     }
 
+    let color_choice = if colored::control::SHOULD_COLORIZE.should_colorize() {
+        simplelog::ColorChoice::Auto
+    } else {
+        simplelog::ColorChoice::Never
+    };
+
     TermLogger::init(
         log_level,
         simplelog::Config::default(),
         TerminalMode::Mixed,
-        simplelog::ColorChoice::Auto,
+        color_choice,
     );
 
     debug!("Argument parsing complete.");
+    // the self-check validates the bundled data, so it has to run before that data is loaded
+    if let Commands::SelfCheck = &cli.command {
+        return commands::self_check_command();
+    }
+    // the flag takes precedence over the environment
+    let platform = match &cli.platform {
+        Some(platform) => Some(platform.parse()?),
+        None => Platform::from_env_override().or_else(Platform::current),
+    };
+    match &platform {
+        Some(platform) if Some(platform) != Platform::current().as_ref() => info!(
+            "Using platform {} instead of the current platform.",
+            platform
+        ),
+        Some(_) => {}
+        None => info!("The current platform isn't supported, so platform overrides won't be used."),
+    }
     let data = SantaData::default();
     let d = data.export();
     // trace!("data: {}", d);
 
+    let config_path = profile_config_path(&cli.profile)?;
     let mut config = if cli.builtin_only {
         info!("loading built-in config because of CLI flag.");
         SantaConfig::default()
     } else {
-        load_config(Path::new(DEFAULT_CONFIG_FILE_PATH))
+        load_config(&config_path)
     };
-    config.log_level = cli.verbose;
+    // environment overrides only change what santa reads; commands that save the config use the file's values
+    let mut file_config = config.clone();
+    if !cli.builtin_only {
+        config.apply_env_overrides();
+    }
+    config.log_level = verbose;
+    if cli.yes {
+        config.confirm_answer = Some(true);
+    } else if cli.no {
+        config.confirm_answer = Some(false);
+    }
 
-    // let mut data = data; // re-declare variable to make it mutable
-    // data.update_from_config(&config);
+    let mut data = data; // re-declare variable to make it mutable
+    data.update_from_config(&config);
+    data.set_platform(&platform);
 
     let mut cache: PackageCache = PackageCache::new();
 
     match &cli.command {
-        Commands::Status { all } => {
+        Commands::Status {
+            all,
+            source,
+            available_only,
+        } => {
             debug!("santa status");
-            commands::status_command(&config, &data, cache, all);
+            commands::status_command(&config, &data, cache, all, source, *available_only);
         }
-        Commands::Install { source } => {
+        Commands::Install {
+            source,
+            parallel,
+            manager_args,
+            dry_run,
+        } => {
             // println!("NYI: santa install {:?}", source);
-            commands::install_command(&config, &data, cache);
+            if let Some(args) = manager_args {
+                // split like a shell would so quoted arguments stay together
+                config.manager_args = match shell_words::split(args) {
+                    Ok(args) => args,
+                    Err(e) => bail!("invalid --manager-args '{}': {}", args, e),
+                };
+            }
+            let history_file =
+                config_file_path(&config_path).with_file_name(history::HISTORY_FILE_NAME);
+            commands::install_command(&config, &data, cache, *parallel, *dry_run, &history_file)?;
         }
-        Commands::Add { source, package } => {
-            println!("NYI: santa add {:?} {:?}", source, package);
-            todo!();
+        Commands::Add { source, packages } => {
+            if cli.builtin_only {
+                bail!("can't add packages when using the built-in config");
+            }
+            let config_file = config_file_path(&config_path);
+            commands::add_command(&mut file_config, &data, &config_file, packages, source)?;
         }
-        Commands::Config { packages, pipe } => {
-            commands::config_command(&config, &data, *packages, cli.builtin_only);
+        Commands::SelfCheck => unreachable!("self-check runs before data is loaded"),
+        Commands::Config {
+            packages,
+            source,
+            pipe,
+            init,
+            force,
+        } => {
+            if *init {
+                let config_file = config_file_path(&config_path);
+                commands::init_config_command(&config_file, *force)?;
+            } else {
+                commands::config_command(
+                    &config,
+                    &data,
+                    *packages,
+                    source,
+                    *pipe,
+                    cli.builtin_only,
+                )?;
+            }
         }
     }
 
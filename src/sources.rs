@@ -4,10 +4,13 @@ use std::collections::{HashMap, HashSet};
 // use cached::proc_macro::cached;
 use colored::*;
 // use anstream::println;
-use dialoguer::{theme::ColorfulTheme, Confirm};
+use dialoguer::{
+    theme::{ColorfulTheme, SimpleTheme, Theme},
+    Confirm,
+};
 use log::{debug, error, info, trace};
 use serde::{Deserialize, Serialize, __private::de::IdentifierDeserializer};
-use subprocess::Exec;
+use subprocess::{Exec, Redirection};
 use tabular::{Row, Table};
 
 use crate::data::{KnownSources, PackageData, Platform, SantaData};
@@ -22,20 +25,113 @@ const MACHINE_KIND: &str = if cfg!(windows) {
     "unknown"
 };
 
+/// Returns an Exec that runs a command using the platform's shell.
+fn shell_exec(command: &str) -> Exec {
+    if MACHINE_KIND != "windows" {
+        Exec::shell(command)
+    } else {
+        Exec::cmd("pwsh.exe").args(&[
+            "-NonInteractive",
+            "-NoLogo",
+            "-NoProfile",
+            "-Command",
+            command,
+        ])
+    }
+}
+
+/// Quotes an argument for the platform's shell, unless it only contains characters that are safe unquoted.
+fn quote_arg(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=./:@,+".contains(c));
+    if safe {
+        arg.to_string()
+    } else if MACHINE_KIND != "windows" {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    } else {
+        format!("'{}'", arg.replace('\'', "''"))
+    }
+}
+
+/// Returns true if a program can be found in one of the directories in a PATH-style list. On Windows, the
+/// extensions in PATHEXT are also tried.
+fn find_on_path(program: &str, path: &std::ffi::OsStr) -> bool {
+    let extensions: Vec<String> = if MACHINE_KIND == "windows" {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT;.PS1".to_string())
+            .split(';')
+            .map(|ext| ext.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    std::env::split_paths(path).any(|dir| {
+        let candidate = dir.join(program);
+        candidate.is_file()
+            || extensions
+                .iter()
+                .any(|ext| dir.join(format!("{}{}", program, ext)).is_file())
+    })
+}
+
+/// Runs a command and returns the outcome along with the command's output.
+fn run_command(command: String) -> (InstallOutcome, String) {
+    match shell_exec(&command).capture() {
+        Ok(data) => {
+            let val = data.stdout_str();
+            if data.exit_status.success() {
+                (InstallOutcome::Succeeded { command }, val)
+            } else {
+                let outcome = InstallOutcome::Failed {
+                    command,
+                    error: format!("{:?}", data.exit_status),
+                };
+                (outcome, val)
+            }
+        }
+        Err(e) => {
+            error!("Subprocess error: {}", e);
+            let outcome = InstallOutcome::Failed {
+                command,
+                error: e.to_string(),
+            };
+            (outcome, String::new())
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PackageCache {
     pub cache: HashMap<String, Vec<String>>,
+    /// Errors from sources whose check command failed, keyed the same way as the cache.
+    pub failures: HashMap<String, String>,
 }
 
 impl PackageCache {
     pub fn new() -> Self {
         let map: HashMap<String, Vec<String>> = HashMap::new();
-        PackageCache { cache: map }
+        PackageCache {
+            cache: map,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Returns the cache key for a source. The key includes the resolved check command so that sources with the same
+    /// name but different commands, e.g. because of platform overrides, are cached separately.
+    pub fn key_for(source: &PackageSource) -> String {
+        format!("{}|{}", source.name_str(), source.check_command())
+    }
+
+    /// Returns the error from a source's check command, if it failed.
+    pub fn failure(&self, source: &PackageSource) -> Option<&String> {
+        self.failures.get(&PackageCache::key_for(source))
     }
 
     /// Checks for a package in the cache. This accesses the cache only, and will not modify it.
     pub fn check(&self, source: &PackageSource, pkg: &str) -> bool {
-        match self.cache.get(&source.name_str()) {
+        match self.cache.get(&PackageCache::key_for(source)) {
             Some(pkgs) => pkgs.contains(&pkg.to_string()),
             _ => {
                 debug!("No package cache for {}", source);
@@ -44,32 +140,110 @@ impl PackageCache {
         }
     }
 
+    /// Queries a source for its installed packages and caches them. A source that is already cached will not be
+    /// queried again.
     pub fn cache_for(&mut self, source: &PackageSource) {
+        if self.cache.contains_key(&PackageCache::key_for(source)) {
+            trace!("Already cached data for {}", source);
+            return;
+        }
         info!("Caching data for {}", source);
-        let pkgs = source.packages();
-        self.cache.insert(source.name_str(), pkgs.clone());
+        // check commands are often pipelines, which succeed even if the package manager is missing, so check for
+        // the package manager first
+        if !source.is_available() {
+            self.failures.insert(
+                PackageCache::key_for(source),
+                format!("`{}` not found on PATH", source.program()),
+            );
+            self.cache.insert(PackageCache::key_for(source), Vec::new());
+            return;
+        }
+        let pkgs = match source.packages() {
+            Ok(pkgs) => pkgs,
+            Err(e) => {
+                self.failures.insert(PackageCache::key_for(source), e);
+                Vec::new()
+            }
+        };
+        self.cache.insert(PackageCache::key_for(source), pkgs);
     }
 
     /// Returns all packages for a PackageSource. This will call the PackageSource's check_command and populate the cache if needed.
     /// If the PackageSource can't be found, or the cache population fails, then None will be returned.
     pub fn packages_for(cache: &mut PackageCache, source: &PackageSource) -> Option<Vec<String>> {
         let c = cache.clone();
-        match c.cache.get(&source.name_str()) {
+        match c.cache.get(&PackageCache::key_for(source)) {
             Some(pkgs) => {
                 trace!("Cache hit");
                 Some(pkgs.to_vec())
             }
             None => {
                 debug!("Cache miss, filling cache for {}", source.name);
-                let pkgs = source.packages();
                 cache.cache_for(source);
-                Some(pkgs)
+                cache.cache.get(&PackageCache::key_for(source)).cloned()
                 // None
             }
         }
     }
 }
 
+/// The result of running an install for a single source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InstallOutcome {
+    /// There were no missing packages to install.
+    NothingToInstall,
+    /// The user chose not to run the install command.
+    Skipped { command: String },
+    /// The install command ran successfully.
+    Succeeded { command: String },
+    /// The install command failed to run or exited with an error.
+    Failed { command: String, error: String },
+    /// The install command ran successfully, but the source's post-install command failed.
+    PostInstallFailed {
+        command: String,
+        post_install_command: String,
+        error: String,
+    },
+}
+
+impl InstallOutcome {
+    /// Returns the install command, if one was needed.
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            InstallOutcome::NothingToInstall => None,
+            InstallOutcome::Skipped { command }
+            | InstallOutcome::Succeeded { command }
+            | InstallOutcome::Failed { command, .. }
+            | InstallOutcome::PostInstallFailed { command, .. } => Some(command),
+        }
+    }
+
+    /// Returns the command that needs to be run again to finish the install, if the install failed. If only the
+    /// post-install command failed, that's the command to retry.
+    pub fn retry_command(&self) -> Option<&str> {
+        match self {
+            InstallOutcome::Failed { command, .. } => Some(command),
+            InstallOutcome::PostInstallFailed {
+                post_install_command,
+                ..
+            } => Some(post_install_command),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for InstallOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallOutcome::NothingToInstall => write!(f, "nothing to install"),
+            InstallOutcome::Skipped { .. } => write!(f, "skipped"),
+            InstallOutcome::Succeeded { .. } => write!(f, "succeeded"),
+            InstallOutcome::Failed { .. } => write!(f, "failed"),
+            InstallOutcome::PostInstallFailed { .. } => write!(f, "post-install failed"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct SourceOverride {
     platform: Platform,
@@ -105,9 +279,15 @@ pub struct PackageSource {
     check_command: String,
     /// A string to prepend to every package name for this source.
     pub prepend_to_package_name: Option<String>,
+    /// A command to run once after packages are successfully installed, e.g. to relink or rehash.
+    pub post_install_command: Option<String>,
 
     /// Override the commands per platform.
     pub overrides: Option<Vec<SourceOverride>>,
+    /// The platform that commands are resolved for. This is the current platform unless it's set with
+    /// `SantaData::set_platform`. Without a platform, no overrides apply.
+    #[serde(skip, default = "Platform::current")]
+    pub platform: Option<Platform>,
     // #[serde(skip)]
     // pub _packages: Vec<String>,
 
@@ -121,92 +301,175 @@ impl PackageSource {
     }
 
     // #[cfg(target_os = "windows")]
-    fn exec_check(&self) -> String {
+    /// Runs the check command and returns its output. If the command fails, the error contains an excerpt of
+    /// its stderr.
+    fn exec_check(&self) -> Result<String, String> {
         let check = self.check_command();
 
         debug!("Running shell command: {}", check);
 
-        let ex: Exec = if MACHINE_KIND != "windows" {
-            Exec::shell(check)
-        } else {
-            Exec::cmd("pwsh.exe").args(&[
-                "-NonInteractive",
-                "-NoLogo",
-                "-NoProfile",
-                "-Command",
-                &check,
-            ])
-        };
-
-        match ex.capture() {
+        match shell_exec(&check)
+            .stdout(Redirection::Pipe)
+            .stderr(Redirection::Pipe)
+            .capture()
+        {
             Ok(data) => {
-                let val = data.stdout_str();
-                return val;
+                if data.exit_status.success() {
+                    return Ok(data.stdout_str());
+                }
+                let stderr = data.stderr_str();
+                let excerpt: Vec<&str> = stderr
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .take(3)
+                    .collect();
+                let err = if excerpt.is_empty() {
+                    format!("{:?}", data.exit_status)
+                } else {
+                    excerpt.join(" ")
+                };
+                error!("Check command for {} failed: {}", self.name, err);
+                return Err(err);
             }
             Err(e) => {
                 error!("Subprocess error: {}", e);
-                return "".to_string();
+                return Err(e.to_string());
             }
         }
     }
 
-    pub fn exec_install(&self, config: &SantaConfig, data: &SantaData, packages: Vec<String>) {
+    pub fn exec_install(
+        &self,
+        config: &SantaConfig,
+        data: &SantaData,
+        packages: Vec<String>,
+    ) -> InstallOutcome {
         // let pkgs: Vec<String> = config.clone().groups(data).keys().map(|i| i.to_string()).collect();
         // for (k, v) in config.groups(data) {
         //     println!("To install missing {} packages, run:", self);
         //     println!("{} {}\n", self.install_command, pkgs.join(" "));
         // }
 
-        if !packages.is_empty() {
-            let renamed: Vec<String> = packages.iter().map(|p| data.name_for(p, self)).collect();
-            let install_command = self.install_packages_command(renamed);
-
-            if Confirm::with_theme(&ColorfulTheme::default())
-                .with_prompt(format!("Run '{}'?", install_command))
-                .default(true)
-                .interact()
-                .unwrap()
-            {
-                let ex: Exec;
-
-                let ex: Exec = if MACHINE_KIND != "windows" {
-                    Exec::shell(install_command)
+        match self.install_command_for(config, data, &packages) {
+            Some(install_command) => {
+                if self.confirm_install(config, &install_command) {
+                    let (outcome, output) = self.run_install(install_command);
+                    println!("{}", output);
+                    outcome
                 } else {
-                    Exec::cmd("pwsh.exe").args(&[
-                        "-NonInteractive",
-                        "-NoLogo",
-                        "-NoProfile",
-                        "-Command",
-                        &install_command,
-                    ])
-                };
-                match ex.capture() {
-                    Ok(data) => {
-                        let val = data.stdout_str();
-                        println!("{}", val);
-                    }
-                    Err(e) => {
-                        error!("Subprocess error: {}", e);
-                    }
+                    self.skip_install(install_command)
                 }
-            } else {
-                println!("To install missing {} packages manually, run:", self);
-                println!("{}\n", install_command.bold());
             }
+            None => {
+                println!("No missing packages for {}", self);
+                InstallOutcome::NothingToInstall
+            }
+        }
+    }
+
+    /// Returns the command that installs packages from this source, using the source-specific package names. Returns
+    /// None if there are no packages to install.
+    pub fn install_command_for(
+        &self,
+        config: &SantaConfig,
+        data: &SantaData,
+        packages: &[String],
+    ) -> Option<String> {
+        if packages.is_empty() {
+            return None;
+        }
+        let renamed: Vec<String> = packages
+            .iter()
+            .map(|p| data.resolve_name(p, self))
+            .collect();
+        Some(self.install_packages_command(renamed, &config.manager_args))
+    }
+
+    /// Asks the user whether an install command should be run, unless the config provides an answer.
+    pub fn confirm_install(&self, config: &SantaConfig, install_command: &str) -> bool {
+        if let Some(answer) = config.confirm_answer {
+            debug!("Answering '{}' to confirmation for {}", answer, self);
+            return answer;
+        }
+        let theme: Box<dyn Theme> = if colored::control::SHOULD_COLORIZE.should_colorize() {
+            Box::new(ColorfulTheme::default())
         } else {
-            println!("No missing packages for {}", self);
+            Box::new(SimpleTheme)
+        };
+        Confirm::with_theme(theme.as_ref())
+            .with_prompt(format!("Run '{}'?", install_command))
+            .default(true)
+            .interact()
+            .unwrap()
+    }
+
+    /// Prints the install command so the user can run it manually.
+    pub fn skip_install(&self, install_command: String) -> InstallOutcome {
+        println!("To install missing {} packages manually, run:", self);
+        println!("{}", install_command.bold());
+        if let Some(post) = &self.post_install_command {
+            println!("{}", post.bold());
+        }
+        println!();
+        InstallOutcome::Skipped {
+            command: install_command,
+        }
+    }
+
+    /// Runs an install command, followed by the source's post-install command if the install succeeds. Returns the
+    /// outcome along with the commands' output. Output is returned rather than printed so that installs running
+    /// concurrently don't interleave.
+    pub fn run_install(&self, install_command: String) -> (InstallOutcome, String) {
+        let (outcome, mut output) = run_command(install_command);
+        if let (InstallOutcome::Succeeded { command }, Some(post)) =
+            (&outcome, &self.post_install_command)
+        {
+            debug!("Running post-install command for {}: {}", self, post);
+            let (post_outcome, post_output) = run_command(post.to_string());
+            output.push_str(&post_output);
+            if let InstallOutcome::Failed { error, .. } = post_outcome {
+                let outcome = InstallOutcome::PostInstallFailed {
+                    command: command.to_string(),
+                    post_install_command: post.to_string(),
+                    error,
+                };
+                return (outcome, output);
+            }
         }
+        (outcome, output)
     }
 
-    /// Returns an override for the current platform, if defined.
+    /// Returns an override for the platform that commands are resolved for, if defined.
     pub fn get_override_for_current_platform(&self) -> Option<SourceOverride> {
-        let current = Platform::current();
+        let platform = self.platform.as_ref()?;
         match &self.overrides {
-            Some(overrides) => overrides.iter().find(|&o| o.platform == current).cloned(),
+            Some(overrides) => overrides.iter().find(|&o| &o.platform == platform).cloned(),
             None => None,
         }
     }
 
+    /// Returns true if the package manager's program can be found on the PATH.
+    pub fn is_available(&self) -> bool {
+        let program = self.program();
+        let available = match std::env::var_os("PATH") {
+            Some(path) => !program.is_empty() && find_on_path(&program, &path),
+            None => false,
+        };
+        debug!("{} available: {}", self, available);
+        available
+    }
+
+    /// Returns the package manager's program, i.e. the first word of the shell command. Wrappers like sudo are
+    /// skipped so the package manager itself is returned.
+    pub fn program(&self) -> String {
+        self.shell_command()
+            .split_whitespace()
+            .find(|word| *word != "sudo")
+            .unwrap_or_default()
+            .to_string()
+    }
+
     /// Returns the configured shell command, taking into account any platform overrides.
     pub fn shell_command(&self) -> String {
         match self.get_override_for_current_platform() {
@@ -229,19 +492,24 @@ impl PackageSource {
                     None => self.install_command.to_string(),
                 };
             }
-            None => self.shell_command.to_string(),
+            None => self.install_command.to_string(),
         }
     }
 
-    pub fn install_packages_command(&self, packages: Vec<String>) -> String {
-        format!("{} {}", self.install_command, packages.join(" "))
+    /// Returns the command that installs packages. Any extra arguments for the package manager are quoted for the
+    /// shell and placed between the install command and the package names.
+    pub fn install_packages_command(&self, packages: Vec<String>, extra_args: &[String]) -> String {
+        let mut parts = vec![self.install_command()];
+        parts.extend(extra_args.iter().map(|arg| quote_arg(arg)));
+        parts.extend(packages);
+        parts.join(" ")
     }
 
     /// Returns the configured check command, taking into account any platform overrides.
     pub fn check_command(&self) -> String {
         match self.get_override_for_current_platform() {
             Some(ov) => {
-                debug!("Override found for {}", Platform::current());
+                debug!("Override found for {:?}", self.platform);
                 trace!("Override: {:?}", ov);
                 return match ov.check_command {
                     Some(cmd) => cmd,
@@ -252,13 +520,14 @@ impl PackageSource {
         }
     }
 
-    pub fn packages(&self) -> Vec<String> {
-        let pkg_list = self.exec_check();
+    /// Returns the installed packages reported by the check command, or an error if the check failed.
+    pub fn packages(&self) -> Result<Vec<String>, String> {
+        let pkg_list = self.exec_check()?;
         let lines = pkg_list.lines();
         let packages: Vec<String> = lines.map(|s| self.adjust_package_name(s)).collect();
         debug!("{} - {} packages installed", self.name, packages.len());
         trace!("{:?}", packages);
-        packages
+        Ok(packages)
     }
 
     // pub fn packages_to_install(&self, cache: &PackageCache) -> Vec<String> {
@@ -281,15 +550,24 @@ impl PackageSource {
     //     self.packages().contains(&pkg)
     // }
 
-    pub fn table(
-        &self,
-        pkgs: &Vec<String>,
-        cache: &PackageCache,
-        include_installed: bool,
-    ) -> Table {
+    /// Pairs each package with whether it is installed, ordered with missing packages first, then installed
+    /// packages. Packages are sorted by name within each group.
+    pub fn ordered_by_status(&self, pkgs: &[String], cache: &PackageCache) -> Vec<(String, bool)> {
+        let mut ordered: Vec<(String, bool)> = pkgs
+            .iter()
+            .map(|pkg| (pkg.to_string(), cache.check(self, pkg)))
+            .collect();
+        ordered.sort_by(|(a_name, a_installed), (b_name, b_installed)| {
+            a_installed
+                .cmp(b_installed)
+                .then_with(|| a_name.cmp(b_name))
+        });
+        ordered
+    }
+
+    pub fn table(&self, pkgs: &[String], cache: &PackageCache, include_installed: bool) -> Table {
         let mut table = Table::new("{:<} {:<}");
-        for pkg in pkgs {
-            let installed = cache.check(self, pkg);
+        for (pkg, installed) in self.ordered_by_status(pkgs, cache) {
             let emoji = if installed { "✅" } else { "❌" };
 
             #[allow(clippy::nonminimal_bool)]
@@ -306,3 +584,129 @@ impl std::fmt::Display for PackageSource {
         write!(f, "{} {}", self.emoji, self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, check_command: &str) -> PackageSource {
+        serde_yaml::from_str(&format!(
+            "name: {}\nemoji: 📦\nshell_command: {}\ninstall_command: \"{} install\"\ncheck_command: \"{}\"\n",
+            name, name, name, check_command
+        ))
+        .unwrap()
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn source_with_overrides() -> PackageSource {
+        serde_yaml::from_str(
+            r#"
+name: npm
+emoji: 📦
+shell_command: npm
+install_command: "npm install -g"
+check_command: "ls -1 `npm root -g`"
+overrides:
+  - platform:
+      os: windows
+      arch: x64
+    check_command: "npm root -g | gci -Name"
+    install_command: "npm.cmd install -g"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn commands_are_resolved_for_the_platform() {
+        let mut npm = source_with_overrides();
+        npm.platform = Some("linux/x64".parse().unwrap());
+        assert_eq!(npm.check_command(), "ls -1 `npm root -g`");
+        assert_eq!(npm.shell_command(), "npm");
+        assert_eq!(
+            npm.install_packages_command(strings(&["yarn"]), &[]),
+            "npm install -g yarn"
+        );
+
+        npm.platform = Some("windows/x64".parse().unwrap());
+        assert_eq!(npm.check_command(), "npm root -g | gci -Name");
+        // the override doesn't change the shell command
+        assert_eq!(npm.shell_command(), "npm");
+        assert_eq!(
+            npm.install_packages_command(strings(&["yarn"]), &[]),
+            "npm.cmd install -g yarn"
+        );
+    }
+
+    #[test]
+    fn overrides_are_not_used_without_a_platform() {
+        // e.g. on an architecture that platforms can't describe
+        let mut npm = source_with_overrides();
+        npm.platform = None;
+        assert_eq!(npm.check_command(), "ls -1 `npm root -g`");
+        assert_eq!(
+            npm.install_packages_command(strings(&["yarn"]), &[]),
+            "npm install -g yarn"
+        );
+    }
+
+    #[test]
+    fn extra_args_go_between_the_install_command_and_packages() {
+        let cargo = source("cargo", "cargo install --list");
+        let args = strings(&["--features", "a b", "--locked", "it's"]);
+        assert_eq!(
+            cargo.install_packages_command(strings(&["bat", "sd"]), &args),
+            "cargo install --features 'a b' --locked 'it'\\''s' bat sd"
+        );
+        assert_eq!(
+            cargo.install_packages_command(strings(&["bat"]), &[]),
+            "cargo install bat"
+        );
+    }
+
+    #[test]
+    fn cache_keys_include_the_resolved_check_command() {
+        let one = source("cargo", "cargo install --list");
+        let other = source("cargo", "cargo install --list --root /opt");
+        assert_ne!(PackageCache::key_for(&one), PackageCache::key_for(&other));
+
+        let mut cache = PackageCache::new();
+        cache
+            .cache
+            .insert(PackageCache::key_for(&one), strings(&["bat"]));
+        assert!(cache.check(&one, "bat"));
+        assert!(!cache.check(&other, "bat"));
+
+        // the same source resolved for different platforms
+        let linux = source_with_overrides();
+        let mut windows = source_with_overrides();
+        windows.platform = Some("windows/x64".parse().unwrap());
+        assert_ne!(
+            PackageCache::key_for(&linux),
+            PackageCache::key_for(&windows)
+        );
+    }
+
+    #[test]
+    fn ordered_by_status_puts_missing_packages_first() {
+        let cargo = source("cargo", "cargo install --list");
+        let mut cache = PackageCache::new();
+        cache
+            .cache
+            .insert(PackageCache::key_for(&cargo), strings(&["bat", "zoxide"]));
+
+        let ordered = cargo.ordered_by_status(&strings(&["zoxide", "sd", "bat", "fd"]), &cache);
+        assert_eq!(
+            ordered,
+            vec![
+                ("fd".to_string(), false),
+                ("sd".to_string(), false),
+                ("bat".to_string(), true),
+                ("zoxide".to_string(), true),
+            ]
+        );
+    }
+}
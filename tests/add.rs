@@ -0,0 +1,132 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+const CONFIG: &str = "\
+sources:
+  - brew
+  - nix
+packages:
+  # tools
+  - bat
+";
+
+/// Returns the packages configured for a source, as printed by `config --packages --source`.
+fn packages_for(santa: &Santa, source: &str) -> Vec<String> {
+    let output = santa.run(&["config", "--packages", "--source", source]);
+    assert!(output.status.success());
+    serde_yaml::from_str(&stdout(&output)).unwrap()
+}
+
+#[test]
+fn add_with_source_pins_the_package_to_that_source() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run(&["add", "ripgrep", "--source", "nix"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Added ripgrep (nix only)"));
+
+    // ripgrep is available from brew too, which is preferred unless the package is pinned
+    assert_eq!(packages_for(&santa, "nix"), vec!["ripgrep"]);
+    assert_eq!(packages_for(&santa, "brew"), vec!["bat"]);
+
+    let config: serde_yaml::Value = serde_yaml::from_str(&santa.read(CONFIG_FILE)).unwrap();
+    assert_eq!(config["package_sources"]["ripgrep"], "nix");
+    assert_eq!(config["packages"][1], "ripgrep");
+}
+
+#[test]
+fn add_without_source_uses_source_order() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run(&["add", "ripgrep"]);
+    assert!(output.status.success());
+    assert_eq!(packages_for(&santa, "brew"), vec!["bat", "ripgrep"]);
+    assert!(packages_for(&santa, "nix").is_empty());
+    assert!(!santa.read(CONFIG_FILE).contains("package_sources"));
+}
+
+#[test]
+fn add_rejects_unknown_sources() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run(&["add", "ripgrep", "--source", "nope"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("unknown source 'nope'"));
+    assert_eq!(santa.read(CONFIG_FILE), CONFIG);
+}
+
+#[test]
+fn add_warns_that_comments_are_lost() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run(&["add", "ripgrep"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("comments in"));
+    let config = santa.read(CONFIG_FILE);
+    assert!(!config.contains('#'));
+    assert!(!config.contains("custom_sources"));
+}
+
+#[test]
+fn add_does_not_save_environment_overrides() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa
+        .command()
+        .args(["add", "ripgrep"])
+        .env("SANTA_PACKAGES", "a,b")
+        .env("SANTA_SOURCES", "cargo")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let config: serde_yaml::Value = serde_yaml::from_str(&santa.read(CONFIG_FILE)).unwrap();
+    assert_eq!(
+        config["packages"],
+        serde_yaml::from_str::<serde_yaml::Value>("[bat, ripgrep]").unwrap()
+    );
+    assert_eq!(
+        config["sources"],
+        serde_yaml::from_str::<serde_yaml::Value>("[brew, nix]").unwrap()
+    );
+}
+
+#[test]
+fn add_reads_packages_from_stdin() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run_with_stdin(&["add", "-"], "ripgrep\n\n# pagers\nbottom\n  fd  \n");
+    assert!(output.status.success());
+    let config: serde_yaml::Value = serde_yaml::from_str(&santa.read(CONFIG_FILE)).unwrap();
+    assert_eq!(
+        config["packages"],
+        serde_yaml::from_str::<serde_yaml::Value>("[bat, ripgrep, bottom, fd]").unwrap()
+    );
+}
+
+#[test]
+fn add_reads_stdin_in_place_among_other_packages() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run_with_stdin(&["add", "bottom", "-", "fd"], "ripgrep\n");
+    assert!(output.status.success());
+    let config: serde_yaml::Value = serde_yaml::from_str(&santa.read(CONFIG_FILE)).unwrap();
+    assert_eq!(
+        config["packages"],
+        serde_yaml::from_str::<serde_yaml::Value>("[bat, bottom, ripgrep, fd]").unwrap()
+    );
+}
+
+#[test]
+fn add_from_empty_stdin_fails() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run_with_stdin(&["add", "-"], "# nothing\n");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no package specified"));
+    assert_eq!(santa.read(CONFIG_FILE), CONFIG);
+}
+
+#[test]
+fn add_resolves_aliases() {
+    let santa = Santa::with_config(CONFIG);
+    let output = santa.run(&["add", "rg"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("rg is an alias for ripgrep"));
+    let config: serde_yaml::Value = serde_yaml::from_str(&santa.read(CONFIG_FILE)).unwrap();
+    assert_eq!(config["packages"][1], "ripgrep");
+}
@@ -0,0 +1,179 @@
+//! Helpers for running the santa binary against a temporary home directory. Package sources in the tests are mock
+//! custom sources whose commands are plain shell commands, so no real package manager is needed.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use serde_yaml::{Mapping, Value};
+use tempfile::TempDir;
+
+/// The config file path, relative to the home directory.
+pub const CONFIG_FILE: &str = ".config/santa/config.yaml";
+
+/// A temporary home directory to run santa in.
+pub struct Santa {
+    home: TempDir,
+}
+
+impl Santa {
+    /// Creates a home directory without a config file.
+    pub fn new() -> Self {
+        Santa {
+            home: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    /// Creates a home directory with a config file.
+    pub fn with_config(config: &str) -> Self {
+        let santa = Santa::new();
+        santa.write(CONFIG_FILE, config);
+        santa
+    }
+
+    pub fn home(&self) -> &Path {
+        self.home.path()
+    }
+
+    /// Returns the path of a file relative to the home directory.
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.home().join(relative)
+    }
+
+    pub fn write(&self, relative: &str, contents: &str) {
+        let path = self.path(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, relative: &str) -> String {
+        fs::read_to_string(self.path(relative)).unwrap()
+    }
+
+    /// Returns a command that runs santa with this home directory and without any santa settings from the
+    /// environment.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_santa"));
+        command.env("HOME", self.home());
+        for (name, _) in std::env::vars() {
+            if name.starts_with("SANTA_") {
+                command.env_remove(name);
+            }
+        }
+        command
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .current_dir(self.home());
+        command
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command().args(args).output().unwrap()
+    }
+
+    /// Runs santa with `input` piped to stdin.
+    pub fn run_with_stdin(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self
+            .command()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+}
+
+/// A custom source whose commands are plain shell commands. By default, packages are installed by appending them to
+/// `<name>-installed` in the home directory, and the check command lists the packages in that file along with
+/// `preinstalled`.
+#[derive(Clone)]
+pub struct MockSource {
+    pub name: String,
+    fields: Vec<(String, Value)>,
+}
+
+impl MockSource {
+    pub fn new(name: &str) -> Self {
+        MockSource {
+            name: name.to_string(),
+            fields: Vec::new(),
+        }
+        .field("emoji", "🧪")
+        .field("shell_command", "sh")
+        .field(
+            "install_command",
+            &format!(
+                "sh -c 'for p; do echo $p >> \"$HOME/{}-installed\"; done' {}",
+                name, name
+            ),
+        )
+        .field(
+            "check_command",
+            &format!(
+                "echo preinstalled; cat \"$HOME/{}-installed\" 2>/dev/null || true",
+                name
+            ),
+        )
+    }
+
+    /// Sets a field of the source definition, replacing any existing value.
+    pub fn field(self, name: &str, value: &str) -> Self {
+        self.field_value(name, value.into())
+    }
+
+    /// Sets a field of the source definition to a value given as YAML, e.g. a list of overrides.
+    pub fn field_yaml(self, name: &str, yaml: &str) -> Self {
+        self.field_value(name, serde_yaml::from_str(yaml).unwrap())
+    }
+
+    fn field_value(mut self, name: &str, value: Value) -> Self {
+        self.fields.retain(|(n, _)| n != name);
+        self.fields.push((name.to_string(), value));
+        self
+    }
+
+    fn to_yaml(&self) -> Value {
+        let mut map = Mapping::new();
+        map.insert("name".into(), self.name.as_str().into());
+        for (name, value) in &self.fields {
+            map.insert(name.as_str().into(), value.clone());
+        }
+        Value::Mapping(map)
+    }
+}
+
+/// Returns a config that uses mock sources, in order of preference. Each package is pinned to the source it's paired
+/// with, since mock sources aren't in the package catalog.
+pub fn mock_config(sources: &[MockSource], packages: &[(&str, &str)]) -> String {
+    let mut config = Mapping::new();
+    let names: Vec<Value> = sources.iter().map(|s| s.name.as_str().into()).collect();
+    config.insert("sources".into(), Value::Sequence(names));
+    let pkgs: Vec<Value> = packages.iter().map(|(p, _)| (*p).into()).collect();
+    config.insert("packages".into(), Value::Sequence(pkgs));
+    let mut pins = Mapping::new();
+    for (package, source) in packages {
+        pins.insert((*package).into(), (*source).into());
+    }
+    config.insert("package_sources".into(), Value::Mapping(pins));
+    let custom: Vec<Value> = sources.iter().map(|s| s.to_yaml()).collect();
+    config.insert("custom_sources".into(), Value::Sequence(custom));
+    serde_yaml::to_string(&Value::Mapping(config)).unwrap()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
@@ -0,0 +1,126 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+#[test]
+fn config_packages_can_be_filtered_by_source() {
+    let config = mock_config(
+        &[MockSource::new("one"), MockSource::new("two")],
+        &[("foo", "one"), ("bar", "two"), ("baz", "one")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["config", "--packages", "--source", "one"]);
+    assert!(output.status.success());
+    let packages: Vec<String> = serde_yaml::from_str(&stdout(&output)).unwrap();
+    assert_eq!(packages, vec!["foo", "baz"]);
+}
+
+#[test]
+fn config_packages_reports_unknown_sources() {
+    let santa = Santa::with_config(&mock_config(&[MockSource::new("one")], &[("foo", "one")]));
+    let output = santa.run(&["config", "--packages", "--source", "nope"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("unknown source 'nope'"));
+}
+
+#[test]
+fn environment_overrides_take_precedence_over_the_config_file() {
+    let santa = Santa::with_config("sources: [brew]\npackages: [bat]\n");
+    let output = santa
+        .command()
+        .args(["config", "--packages", "--source", "nix"])
+        .env("SANTA_SOURCES", "nix")
+        .env("SANTA_PACKAGES", "ripgrep,bottom")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let packages: Vec<String> = serde_yaml::from_str(&stdout(&output)).unwrap();
+    assert_eq!(packages, vec!["ripgrep", "bottom"]);
+}
+
+#[test]
+fn invalid_log_level_is_ignored_with_a_warning() {
+    let santa = Santa::with_config("sources: [brew]\npackages: [bat]\n");
+    let output = santa
+        .command()
+        .args(["config"])
+        .env("SANTA_LOG_LEVEL", "loud")
+        .env("CLICOLOR_FORCE", "1")
+        .arg("--no-color")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("warning: ignoring invalid SANTA_LOG_LEVEL: loud"));
+}
+
+const DEFAULT_CONFIG: &str = include_str!("../data/santa-config.yaml");
+
+#[test]
+fn init_writes_the_default_config() {
+    let santa = Santa::new();
+    let output = santa.run(&["config", "--init"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("Wrote default config to "));
+    assert_eq!(santa.read(CONFIG_FILE), DEFAULT_CONFIG);
+}
+
+#[test]
+fn init_does_not_overwrite_without_force() {
+    let santa = Santa::with_config("sources: [brew]\npackages: [bat]\n");
+    let output = santa.run(&["config", "--init"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("use --force to overwrite it"));
+    assert_eq!(
+        santa.read(CONFIG_FILE),
+        "sources: [brew]\npackages: [bat]\n"
+    );
+
+    let output = santa.run(&["config", "--init", "--force"]);
+    assert!(output.status.success());
+    assert_eq!(santa.read(CONFIG_FILE), DEFAULT_CONFIG);
+}
+
+#[test]
+fn force_requires_init() {
+    let santa = Santa::new();
+    let output = santa.run(&["config", "--force"]);
+    assert!(!output.status.success());
+    assert!(!santa.path(CONFIG_FILE).exists());
+}
+
+/// Returns the packages configured for a source, as printed by `config --packages --source`.
+fn packages_for(output: &std::process::Output) -> Vec<String> {
+    assert!(output.status.success());
+    serde_yaml::from_str(&stdout(output)).unwrap()
+}
+
+#[test]
+fn pipe_output_round_trips_through_the_environment() {
+    let santa = Santa::with_config(
+        "sources: [brew, nix]\npackages: [bat, ripgrep]\npackage_sources:\n  ripgrep: nix\n",
+    );
+    let output = santa.run(&["config", "--pipe"]);
+    assert!(output.status.success());
+    let exports = stdout(&output);
+    assert!(exports.contains("export SANTA_PACKAGE_SOURCES='ripgrep=nix'\n"));
+
+    // a home with a different config file reads the same config once the exports are sourced
+    let other = Santa::with_config("sources: [cargo]\npackages: [fd]\n");
+    for source in ["brew", "nix"] {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg("eval \"$EXPORTS\"; exec \"$SANTA\" config --packages --source \"$1\"")
+            .arg("sh")
+            .arg(source)
+            .env("HOME", other.home())
+            .env("EXPORTS", &exports)
+            .env("SANTA", env!("CARGO_BIN_EXE_santa"))
+            .output()
+            .unwrap();
+        let expected = packages_for(&santa.run(&["config", "--packages", "--source", source]));
+        assert_eq!(packages_for(&output), expected);
+    }
+}
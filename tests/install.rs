@@ -0,0 +1,336 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+const POST_INSTALL: &str = "echo post >> \"$HOME/post-installed\"";
+
+fn post_install_config(install_command: &str) -> String {
+    let source = MockSource::new("mock")
+        .field("install_command", install_command)
+        .field("post_install_command", POST_INSTALL);
+    mock_config(&[source], &[("foo", "mock")])
+}
+
+#[test]
+fn post_install_command_is_printed_when_skipping() {
+    let santa = Santa::with_config(&post_install_config("true"));
+    let output = santa.run(&["install", "--no"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("To install missing 🧪 mock packages manually, run:"));
+    assert!(out.contains(POST_INSTALL));
+    assert!(!santa.path("post-installed").exists());
+}
+
+#[test]
+fn post_install_command_runs_after_successful_install() {
+    let santa = Santa::with_config(&post_install_config("true"));
+    let output = santa.run(&["install", "--yes"]);
+    assert!(output.status.success());
+    assert_eq!(santa.read("post-installed"), "post\n");
+}
+
+#[test]
+fn post_install_command_does_not_run_after_failed_install() {
+    let santa = Santa::with_config(&post_install_config("false"));
+    let output = santa.run(&["install", "--yes"]);
+    assert!(!output.status.success());
+    assert!(!santa.path("post-installed").exists());
+}
+
+#[test]
+fn failed_install_is_reported_with_a_retry_command() {
+    let failing = MockSource::new("broken").field("install_command", "sh -c 'exit 3' broken");
+    let config = mock_config(
+        &[MockSource::new("mock"), failing],
+        &[("foo", "mock"), ("bar", "broken")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["install", "--yes"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("error: 1 install(s) failed"));
+    let out = stdout(&output);
+
+    let summary = out.split("Install summary:").nth(1).unwrap();
+    assert!(summary.contains("succeeded 🧪 mock"));
+    assert!(summary.contains("failed 🧪 broken"));
+    let retry = summary
+        .split("Some installs failed. To retry them, run:")
+        .nth(1)
+        .unwrap();
+    assert_eq!(retry.trim(), "sh -c 'exit 3' broken bar");
+    assert_eq!(santa.read("mock-installed"), "foo\n");
+}
+
+#[test]
+fn failed_post_install_is_reported_with_the_install_command() {
+    let source = MockSource::new("mock").field("post_install_command", "false");
+    let santa = Santa::with_config(&mock_config(&[source], &[("foo", "mock")]));
+    let output = santa.run(&["install", "--yes"]);
+    assert!(!output.status.success());
+    let out = stdout(&output);
+
+    let summary = out.split("Install summary:").nth(1).unwrap();
+    assert!(summary.contains("failed 🧪 mock"));
+    assert!(summary.contains("but 'false' failed"));
+    let retry = summary
+        .split("Some installs failed. To retry them, run:")
+        .nth(1)
+        .unwrap();
+    assert_eq!(retry.trim(), "false");
+
+    let history = santa.read(".config/santa/history.yaml");
+    assert!(history.contains("outcome: post-install failed"));
+    assert!(history.contains("failed_post_install_command: \"false\""));
+    assert!(history.contains("mock-installed"));
+}
+
+fn config_with_failing_check() -> String {
+    let failing = MockSource::new("broken").field("check_command", "echo boom >&2; exit 1");
+    mock_config(
+        &[MockSource::new("mock"), failing],
+        &[("foo", "mock"), ("bar", "broken")],
+    )
+}
+
+#[test]
+fn install_skips_sources_whose_check_failed() {
+    let santa = Santa::with_config(&config_with_failing_check());
+    let output = santa.run(&["install", "--yes"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("🧪 broken check failed — boom; skipping it"));
+    assert_eq!(santa.read("mock-installed"), "foo\n");
+    assert!(!santa.path("broken-installed").exists());
+}
+
+#[test]
+fn dry_run_skips_sources_whose_check_failed() {
+    let santa = Santa::with_config(&config_with_failing_check());
+    let output = santa.run(&["install", "--dry-run"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("🧪 broken check failed — boom; skipping it"));
+    assert!(out.contains("Would install 1 🧪 mock packages: foo"));
+    assert!(!out.contains("bar"));
+}
+
+#[test]
+fn install_skips_sources_whose_package_manager_is_missing() {
+    let missing = MockSource::new("gone")
+        .field("shell_command", "santa-missing-pm")
+        .field("check_command", "santa-missing-pm list | cut -d' ' -f1")
+        .field("install_command", "santa-missing-pm install");
+    let config = mock_config(
+        &[MockSource::new("mock"), missing],
+        &[("foo", "mock"), ("bar", "gone")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["install", "--yes"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(
+        out.contains("🧪 gone check failed — `santa-missing-pm` not found on PATH; skipping it")
+    );
+    assert!(!out.contains("santa-missing-pm install"));
+    assert_eq!(santa.read("mock-installed"), "foo\n");
+}
+
+#[test]
+fn parallel_install_aggregates_results() {
+    let config = mock_config(
+        &[
+            MockSource::new("one"),
+            MockSource::new("two"),
+            MockSource::new("three"),
+        ],
+        &[
+            ("foo", "one"),
+            ("bar", "two"),
+            ("baz", "three"),
+            ("qux", "one"),
+        ],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["install", "--parallel", "--yes"]);
+    assert!(output.status.success());
+
+    assert_eq!(santa.read("one-installed"), "foo\nqux\n");
+    assert_eq!(santa.read("two-installed"), "bar\n");
+    assert_eq!(santa.read("three-installed"), "baz\n");
+
+    let out = stdout(&output);
+    let summary = out.split("Install summary:").nth(1).unwrap();
+    for source in ["one", "two", "three"] {
+        assert!(out.contains(&format!("🧪 {}:", source)));
+        assert!(summary.contains(&format!("succeeded 🧪 {}", source)));
+    }
+    assert!(!summary.contains("failed"));
+}
+
+#[test]
+fn yes_installs_without_prompting() {
+    for flag in ["--yes", "-y", "--no-confirm"] {
+        let santa =
+            Santa::with_config(&mock_config(&[MockSource::new("mock")], &[("foo", "mock")]));
+        let output = santa.run(&["install", flag]);
+        assert!(output.status.success());
+        assert_eq!(santa.read("mock-installed"), "foo\n");
+    }
+}
+
+#[test]
+fn no_skips_installs_without_prompting() {
+    let santa = Santa::with_config(&mock_config(&[MockSource::new("mock")], &[("foo", "mock")]));
+    let output = santa.run(&["install", "--no"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("To install missing 🧪 mock packages manually, run:"));
+    assert!(!santa.path("mock-installed").exists());
+}
+
+#[test]
+fn install_appends_to_the_history_file() {
+    let config = mock_config(
+        &[MockSource::new("mock"), MockSource::new("other")],
+        &[("foo", "mock"), ("bar", "mock"), ("preinstalled", "other")],
+    );
+    let santa = Santa::with_config(&config);
+    assert!(santa.run(&["install", "--yes"]).status.success());
+    assert!(santa.run(&["install", "--no"]).status.success());
+
+    let history: serde_yaml::Value =
+        serde_yaml::from_str(&santa.read(".config/santa/history.yaml")).unwrap();
+    let entries = history.as_sequence().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let first = &entries[0];
+    assert!(first["timestamp"].as_u64().unwrap() > 0);
+    assert_eq!(first["parallel"], false);
+    let sources = first["sources"].as_sequence().unwrap();
+    assert_eq!(sources[0]["source"], "mock");
+    assert_eq!(
+        sources[0]["packages"],
+        serde_yaml::from_str::<serde_yaml::Value>("[foo, bar]").unwrap()
+    );
+    assert!(sources[0]["command"]
+        .as_str()
+        .unwrap()
+        .ends_with("mock foo bar"));
+    assert_eq!(sources[0]["outcome"], "succeeded");
+    assert_eq!(sources[1]["source"], "other");
+    assert_eq!(sources[1]["command"], serde_yaml::Value::Null);
+    assert_eq!(sources[1]["outcome"], "nothing to install");
+
+    // everything was installed by the first run
+    assert_eq!(entries[1]["sources"][0]["outcome"], "nothing to install");
+}
+
+#[test]
+fn install_warns_when_the_history_file_cannot_be_written() {
+    let santa = Santa::with_config(&mock_config(&[MockSource::new("mock")], &[("foo", "mock")]));
+    santa.write(".config/santa/history.yaml", "not: [a, history");
+    let output = santa.run(&["install", "--yes"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("warning: couldn't write install history"));
+    assert_eq!(santa.read("mock-installed"), "foo\n");
+}
+
+#[test]
+fn manager_args_are_split_like_a_shell() {
+    let santa = Santa::with_config(&mock_config(&[MockSource::new("mock")], &[("foo", "mock")]));
+    let output = santa.run(&[
+        "install",
+        "--dry-run",
+        "--manager-args",
+        "--features 'a b' --locked",
+    ]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("mock --features 'a b' --locked foo\n"));
+
+    // the quoted argument reaches the package manager as a single argument
+    let output = santa.run(&["install", "--yes", "--manager-args", "'a b'"]);
+    assert!(output.status.success());
+    assert_eq!(santa.read("mock-installed"), "a b\nfoo\n");
+}
+
+#[test]
+fn unbalanced_manager_args_are_an_error() {
+    let santa = Santa::with_config(&mock_config(&[MockSource::new("mock")], &[("foo", "mock")]));
+    let output = santa.run(&["install", "--dry-run", "--manager-args", "--features 'a b"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid --manager-args"));
+}
+
+#[test]
+fn install_warns_about_packages_pinned_to_unavailable_sources() {
+    let missing = MockSource::new("gone").field("shell_command", "santa-missing-pm");
+    let config = mock_config(
+        &[MockSource::new("mock"), missing],
+        &[("foo", "mock"), ("bar", "gone")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["install", "--yes"]);
+    assert!(output.status.success());
+    let err = stderr(&output);
+    assert!(err.contains("warning: bar can't be installed here; none of its sources are available"));
+    assert!(!err.contains("foo can't be installed"));
+    assert_eq!(santa.read("mock-installed"), "foo\n");
+
+    // nothing was attempted with the unavailable source
+    assert!(!santa.path("gone-installed").exists());
+    let out = stdout(&output);
+    assert!(!out.contains("failed 🧪 gone"));
+    assert!(!out.contains("To retry them"));
+    let history: serde_yaml::Value =
+        serde_yaml::from_str(&santa.read(".config/santa/history.yaml")).unwrap();
+    let sources = history[0]["sources"].as_sequence().unwrap();
+    assert!(sources.iter().all(|source| source["source"] != "gone"));
+}
+
+/// Returns the paths of all files and directories under a directory, relative to it and sorted.
+fn list_files(dir: &std::path::Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            files.push(path.strip_prefix(dir).unwrap().display().to_string());
+            if path.is_dir() {
+                pending.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn dry_run_prints_install_commands_without_creating_files() {
+    let prefixed = MockSource::new("prefixed")
+        .field("install_command", "mockpm install --quiet")
+        .field("prepend_to_package_name", "pkgs.");
+    let config = mock_config(
+        &[MockSource::new("mock"), prefixed],
+        &[("foo", "mock"), ("bar", "prefixed"), ("baz", "prefixed")],
+    );
+    let santa = Santa::with_config(&config);
+    let before = list_files(santa.home());
+
+    let output = santa.run(&["install", "--dry-run", "--manager-args", "--force"]);
+    assert!(output.status.success());
+    assert_eq!(list_files(santa.home()), before);
+    assert!(!santa.path(".config/santa/history.yaml").exists());
+
+    let out = stdout(&output);
+    let lines: Vec<&str> = out.lines().filter(|line| !line.is_empty()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "Would install 1 🧪 mock packages: foo",
+            "sh -c 'for p; do echo $p >> \"$HOME/mock-installed\"; done' mock --force foo",
+            "Would install 2 🧪 prefixed packages: bar, baz",
+            "mockpm install --quiet --force pkgs.bar pkgs.baz",
+        ]
+    );
+}
@@ -0,0 +1,87 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+const OVERRIDES: &str = r#"
+- platform:
+    os: windows
+    arch: x64
+  install_command: "winpm install"
+"#;
+
+fn config() -> String {
+    let source = MockSource::new("mock")
+        .field("install_command", "unixpm install")
+        .field_yaml("overrides", OVERRIDES);
+    mock_config(&[source], &[("foo", "mock")])
+}
+
+#[test]
+fn platform_flag_resolves_commands_for_that_platform() {
+    let santa = Santa::with_config(&config());
+    let output = santa.run(&["--platform", "windows/x64", "install", "--dry-run"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("winpm install foo"));
+    assert!(!out.contains("unixpm"));
+
+    let output = santa.run(&["install", "--dry-run"]);
+    assert!(stdout(&output).contains("unixpm install foo"));
+}
+
+#[test]
+fn platform_flag_takes_precedence_over_the_environment() {
+    let santa = Santa::with_config(&config());
+    let output = santa
+        .command()
+        .args(["--platform", "linux/x64", "install", "--dry-run"])
+        .env("SANTA_PLATFORM", "windows/x64")
+        .output()
+        .unwrap();
+    assert!(stdout(&output).contains("unixpm install foo"));
+
+    let output = santa
+        .command()
+        .args(["install", "--dry-run"])
+        .env("SANTA_PLATFORM", "windows/x64")
+        .output()
+        .unwrap();
+    assert!(stdout(&output).contains("winpm install foo"));
+}
+
+#[test]
+fn invalid_platform_is_an_error() {
+    let santa = Santa::with_config(&config());
+    let output = santa.run(&["--platform", "plan9/x64", "status"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("unknown OS 'plan9'"));
+}
+
+#[test]
+fn invalid_platform_in_the_environment_is_reported_once() {
+    let santa = Santa::with_config(&config());
+    let output = santa
+        .command()
+        .args(["--no-color", "status"])
+        .env("SANTA_PLATFORM", "bogus")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let err = stderr(&output);
+    assert!(err.contains("warning: ignoring invalid SANTA_PLATFORM: "));
+    assert_eq!(err.matches("ignoring invalid SANTA_PLATFORM").count(), 1);
+}
+
+#[test]
+fn platform_is_not_passed_to_package_managers() {
+    let source = MockSource::new("mock").field(
+        "check_command",
+        "echo \"${SANTA_PLATFORM:-unset}\" > \"$HOME/platform\"",
+    );
+    let santa = Santa::with_config(&mock_config(&[source], &[("foo", "mock")]));
+    let output = santa.run(&["--platform", "linux/x64", "status"]);
+    assert!(output.status.success());
+    assert_eq!(santa.read("platform"), "unset\n");
+}
@@ -0,0 +1,49 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+const DEFAULT: &str = "sources: [brew]\npackages: [bat]\n";
+const WORK: &str = "sources: [nix]\npackages: [ripgrep]\n";
+
+#[test]
+fn profile_reads_and_updates_the_profile_config() {
+    let santa = Santa::with_config(DEFAULT);
+    santa.write(".config/santa/work.yaml", WORK);
+
+    let output = santa.run(&[
+        "--profile",
+        "work",
+        "config",
+        "--packages",
+        "--source",
+        "nix",
+    ]);
+    assert!(output.status.success());
+    let packages: Vec<String> = serde_yaml::from_str(&stdout(&output)).unwrap();
+    assert_eq!(packages, vec!["ripgrep"]);
+
+    let output = santa.run(&["--profile", "work", "add", "bottom"]);
+    assert!(output.status.success());
+    let work: serde_yaml::Value =
+        serde_yaml::from_str(&santa.read(".config/santa/work.yaml")).unwrap();
+    assert_eq!(
+        work["packages"],
+        serde_yaml::from_str::<serde_yaml::Value>("[ripgrep, bottom]").unwrap()
+    );
+    assert_eq!(santa.read(CONFIG_FILE), DEFAULT);
+}
+
+#[test]
+fn reserved_profile_names_are_rejected() {
+    let santa = Santa::with_config(DEFAULT);
+    santa.write(".config/santa/history.yaml", "[]\n");
+    for name in ["history", "../config", ""] {
+        let output = santa.run(&["--profile", name, "add", "bottom"]);
+        assert!(!output.status.success(), "profile '{}' was accepted", name);
+    }
+    assert!(stderr(&santa.run(&["--profile", "history", "config"])).contains("is reserved"));
+    assert_eq!(santa.read(".config/santa/history.yaml"), "[]\n");
+    assert_eq!(santa.read(CONFIG_FILE), DEFAULT);
+}
@@ -0,0 +1,20 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+#[test]
+fn self_check_passes_on_bundled_data() {
+    let santa = Santa::new();
+    let output = santa.run(&["self-check"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Bundled data is valid."));
+}
+
+#[test]
+fn self_check_does_not_load_the_config() {
+    let santa = Santa::with_config("sources: [brew\n");
+    let output = santa.run(&["self-check"]);
+    assert!(output.status.success());
+}
@@ -0,0 +1,224 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+
+fn three_sources() -> Santa {
+    let config = mock_config(
+        &[
+            MockSource::new("one"),
+            MockSource::new("two"),
+            MockSource::new("three"),
+        ],
+        &[("foo", "one"), ("bar", "two"), ("baz", "three")],
+    );
+    Santa::with_config(&config)
+}
+
+#[test]
+fn status_filters_by_multiple_sources() {
+    let santa = three_sources();
+    for args in [
+        &["status", "--source", "one,three"][..],
+        &["status", "--source", "one", "--source", "three"][..],
+    ] {
+        let output = santa.run(args);
+        assert!(output.status.success());
+        let out = stdout(&output);
+        assert!(out.contains("🧪 one"));
+        assert!(out.contains("🧪 three"));
+        assert!(!out.contains("🧪 two"));
+    }
+}
+
+#[test]
+fn status_warns_about_unknown_sources() {
+    let santa = three_sources();
+    let output = santa.run(&["status", "--source", "one,nope"]);
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("warning: unknown source 'nope'"));
+    let out = stdout(&output);
+    assert!(out.contains("🧪 one"));
+    assert!(!out.contains("🧪 two"));
+}
+
+#[test]
+fn status_all_lists_missing_packages_before_installed_ones() {
+    let config = mock_config(
+        &[MockSource::new("mock")],
+        &[
+            ("preinstalled", "mock"),
+            ("zoxide", "mock"),
+            ("bat", "mock"),
+        ],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["status", "--all"]);
+    assert!(output.status.success());
+    let lines: Vec<String> = stdout(&output)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "🧪 mock (3 packages total, 2 missing)",
+            "❌ bat",
+            "❌ zoxide",
+            "✅ preinstalled",
+        ]
+    );
+}
+
+#[test]
+fn status_checks_each_source_once() {
+    let counted = |name: &str| {
+        MockSource::new(name).field(
+            "check_command",
+            &format!("echo {} >> \"$HOME/checks\"; echo preinstalled", name),
+        )
+    };
+    let config = mock_config(
+        &[counted("one"), counted("two")],
+        &[("foo", "one"), ("bar", "one"), ("baz", "two")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["status", "--all", "--source", "one,two,one"]);
+    assert!(output.status.success());
+    let mut checks: Vec<String> = santa.read("checks").lines().map(String::from).collect();
+    checks.sort();
+    assert_eq!(checks, vec!["one", "two"]);
+}
+
+#[test]
+fn status_reports_failed_checks() {
+    let failing = MockSource::new("broken").field("check_command", "echo boom >&2; exit 1");
+    let config = mock_config(
+        &[MockSource::new("mock"), failing],
+        &[("foo", "mock"), ("bar", "broken")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["status"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("🧪 broken check failed — boom"));
+    assert!(out.contains("🧪 mock (1 packages total, 1 missing)"));
+    assert!(out.contains("❌ foo"));
+    assert!(!out.contains("bar"));
+}
+
+#[test]
+fn status_reports_missing_package_managers_with_piped_checks() {
+    // the pipeline exits 0 because cut succeeds, even though the package manager isn't installed
+    let missing = MockSource::new("gone")
+        .field("shell_command", "santa-missing-pm")
+        .field("check_command", "santa-missing-pm list | cut -d' ' -f1");
+    let config = mock_config(
+        &[MockSource::new("mock"), missing],
+        &[("foo", "mock"), ("bar", "gone")],
+    );
+    let santa = Santa::with_config(&config);
+    let output = santa.run(&["status"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("🧪 gone check failed — `santa-missing-pm` not found on PATH"));
+    assert!(!out.contains("bar"));
+}
+
+#[test]
+fn status_has_no_color_when_disabled() {
+    let failing = MockSource::new("broken").field("check_command", "exit 1");
+    let config = mock_config(
+        &[MockSource::new("mock"), failing],
+        &[("foo", "mock"), ("bar", "broken")],
+    );
+    let santa = Santa::with_config(&config);
+    let status = |flags: &[&str], env: &[(&str, &str)]| {
+        let mut command = santa.command();
+        command.args(["status", "--all", "--source", "mock,nope,broken"]);
+        command.args(flags).env("CLICOLOR_FORCE", "1");
+        for (name, value) in env {
+            command.env(name, value);
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        format!("{}{}", stdout(&output), stderr(&output))
+    };
+
+    // colors are forced on, so without a setting to disable them the output is colored
+    assert!(status(&[], &[]).contains('\x1b'));
+    assert!(!status(&["--no-color"], &[]).contains('\x1b'));
+    assert!(!status(&[], &[("NO_COLOR", "1")]).contains('\x1b'));
+}
+
+#[test]
+fn status_available_only_hides_sources_that_are_not_on_the_path() {
+    let config = mock_config(
+        &[
+            MockSource::new("here").field("shell_command", "mockpm"),
+            MockSource::new("sudo").field("shell_command", "sudo mockpm"),
+            MockSource::new("gone").field("shell_command", "santa-missing-pm"),
+        ],
+        &[("foo", "here"), ("bar", "sudo"), ("baz", "gone")],
+    );
+    let santa = Santa::with_config(&config);
+    santa.write("bin/mockpm", "#!/bin/sh\n");
+    let mockpm = santa.path("bin/mockpm");
+    let mut permissions = std::fs::metadata(&mockpm).unwrap().permissions();
+    std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+    std::fs::set_permissions(&mockpm, permissions).unwrap();
+    let path = format!(
+        "{}:{}",
+        santa.path("bin").display(),
+        std::env::var("PATH").unwrap()
+    );
+
+    let status = |args: &[&str]| {
+        let output = santa
+            .command()
+            .args(args)
+            .env("PATH", &path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        stdout(&output)
+    };
+
+    let out = status(&["status", "--all"]);
+    for source in ["here", "sudo", "gone"] {
+        assert!(out.contains(&format!("🧪 {}", source)));
+    }
+
+    let out = status(&["status", "--all", "--available-only"]);
+    assert!(out.contains("🧪 here"));
+    assert!(out.contains("🧪 sudo"));
+    assert!(!out.contains("🧪 gone"));
+}
+
+#[test]
+fn disabled_sources_are_not_checked_or_installed() {
+    let config = mock_config(
+        &[MockSource::new("one"), MockSource::new("two")],
+        &[("foo", "one"), ("bar", "two")],
+    );
+    let santa = Santa::with_config(&format!("{}disabled_sources: [two]\n", config));
+
+    let output = santa.run(&["status", "--all"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("🧪 one"));
+    assert!(!out.contains("🧪 two"));
+
+    let output = santa.run(&["install", "--yes"]);
+    assert!(output.status.success());
+    assert_eq!(santa.read("one-installed"), "foo\n");
+    assert!(!santa.path("two-installed").exists());
+
+    // the source is still in the config
+    let output = santa.run(&["config"]);
+    let config: serde_yaml::Value = serde_yaml::from_str(&stdout(&output)).unwrap();
+    assert_eq!(config["sources"][1], "two");
+    assert_eq!(config["disabled_sources"][0], "two");
+}